@@ -0,0 +1,77 @@
+use std::{
+    collections::HashMap,
+    fmt::Write,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+#[path = "src/git_dates.rs"]
+mod git_dates;
+
+fn main() {
+    // `include_dir!` can't tell cargo about the files it embeds, so rebuild whenever they change:
+    if std::env::var_os("CARGO_FEATURE_EMBED").is_some() {
+        println!("cargo:rerun-if-changed=pages");
+        // Commits, checkouts and resets change the git dates without touching the pages:
+        for path in git_state_files() {
+            println!("cargo:rerun-if-changed={}", path.display());
+        }
+        write_embedded_dates();
+    } else {
+        println!("cargo:rerun-if-changed=build.rs");
+    }
+}
+
+/// The files git updates when the history of the pages may have changed: the index, `HEAD`, the
+/// ref it points at and the packed refs. Only those that exist, as cargo reruns the build script
+/// every time for missing ones.
+fn git_state_files() -> Vec<PathBuf> {
+    let git_directory = Path::new(".git");
+    let mut paths = vec![
+        git_directory.join("index"),
+        git_directory.join("HEAD"),
+        git_directory.join("packed-refs"),
+    ];
+    let head = std::fs::read_to_string(git_directory.join("HEAD")).unwrap_or_default();
+    if let Some(reference) = head.trim().strip_prefix("ref: ") {
+        paths.push(git_directory.join(reference));
+    }
+    paths.retain(|path| path.exists());
+    paths
+}
+
+/// Writes `embedded_dates.rs` for `src/content.rs`, as the embedded pages have no git history to
+/// read at runtime.
+fn write_embedded_dates() {
+    let git_dates = git_dates::read_git_dates(Path::new("pages")).unwrap_or_else(|error| {
+        println!("cargo:warning=Embedding no git dates, could not read git history: {error}");
+        HashMap::new()
+    });
+    let seconds = |time: SystemTime| time.duration_since(UNIX_EPOCH).unwrap().as_secs();
+
+    let mut generated = format!(
+        "/// When the pages were embedded, the dates of files without recorded times.\n\
+        const BUILD_TIME: u64 = {};\n\n\
+        /// `(path, created, last_modified)` commit times of the embedded files, see\n\
+        /// [`Content::git_dates`].\n\
+        static EMBEDDED_GIT_DATES: &[(&str, u64, u64)] = &[\n",
+        seconds(SystemTime::now())
+    );
+    // Only the pages' dates are read from git:
+    for (path, (created, last_modified)) in git_dates
+        .into_iter()
+        .filter(|(path, _)| path.extension().is_some_and(|extension| extension == "md"))
+    {
+        let _ = writeln!(
+            generated,
+            "    ({:?}, {}, {}),",
+            path.to_string_lossy(),
+            seconds(created),
+            seconds(last_modified)
+        );
+    }
+    generated.push_str("];\n");
+
+    let out_dir = PathBuf::from(std::env::var_os("OUT_DIR").unwrap());
+    std::fs::write(out_dir.join("embedded_dates.rs"), generated).unwrap();
+}
@@ -5,10 +5,13 @@
 function build_zip() {
   mkdir "./target/zip"
 
-  cargo build --release --target=x86_64-unknown-linux-musl
+  # The pages are embedded, so only the binary and its config need shipping:
+  just precompress
+  cargo build --release --features embed --target=x86_64-unknown-linux-musl
 
   cp "./target/x86_64-unknown-linux-musl/release/auxv-dot-org" "./target/zip/auxv-dot-org"
-  cp -r "./pages" "./target/zip/pages"
+  cp "./site.toml" "./target/zip/site.toml"
+  cp ./auxv-dot-org.service ./auxv-dot-org-*.socket "./target/zip/"
 
   (cd "./target/zip" && zip -r "./auxv-dot-org.zip" "." -x "./auxv-dot-org.zip")
 }
@@ -20,14 +23,30 @@ function deploy_zip() {
 
   scp -i "$secret_path" "./target/zip/auxv-dot-org.zip" "$user@$host:~/auxv-dot-org.zip"
 
-  # sudo to remove ./auxv-dot-org becaues lets_encrypt_cache is root
+  # The lets_encrypt_cache lives in /var/lib/auxv-dot-org, so nothing needs carrying over
+  # The service runs as the auxv-dot-org user, which can't reach the home directory, so the binary
+  # and config are installed where it can read them.
+  # The old build keeps serving until systemd stops it, which lets its requests finish while the
+  # new one already queues up on the (systemd owned) sockets. Until the sockets are active (the
+  # first deploy using them) the old build binds ports 80 and 443 itself, so it's stopped first.
+  # The old build's directory may still hold a lets_encrypt_cache owned by root:
   ssh -i "$secret_path" "$user@$host" "mkdir ./auxv-dot-org-tmp \
   && unzip ./auxv-dot-org -d ./auxv-dot-org-tmp \
-  && cp -r ./auxv-dot-org/lets_encrypt_cache ./auxv-dot-org-tmp/lets_encrypt_cache \
-  && sudo -S rm -r ./auxv-dot-org \
+  && sudo -S rm -rf ./auxv-dot-org-old \
+  ; mv ./auxv-dot-org ./auxv-dot-org-old \
   ; mv ./auxv-dot-org-tmp ./auxv-dot-org \
+  && { id auxv-dot-org > /dev/null 2>&1 \
+    || sudo -S useradd --system --no-create-home --shell /usr/sbin/nologin auxv-dot-org; } \
+  && sudo -S install -D -m 755 ./auxv-dot-org/auxv-dot-org /usr/local/bin/auxv-dot-org \
+  && sudo -S install -D -m 644 ./auxv-dot-org/site.toml /etc/auxv-dot-org/site.toml \
+  && sudo -S cp ./auxv-dot-org/auxv-dot-org.service ./auxv-dot-org/*.socket /etc/systemd/system/ \
+  && sudo -S systemctl daemon-reload \
+  && { systemctl is-active --quiet auxv-dot-org-https.socket \
+    || sudo -S systemctl stop auxv-dot-org; } \
+  && sudo -S systemctl enable --now auxv-dot-org-http.socket auxv-dot-org-https.socket \
+  && sudo -S systemctl enable auxv-dot-org \
   && sudo -S systemctl restart auxv-dot-org \
-  && rm ./auxv-dot-org.zip"
+  && sudo -S rm -r ./auxv-dot-org-old ./auxv-dot-org.zip"
 
 }
 
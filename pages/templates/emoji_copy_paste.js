@@ -2,12 +2,12 @@ document.addEventListener('copy', function(e) {
     const selection = window.getSelection();
     if (!selection || selection.rangeCount === 0) return;
 
-    // Check if the selection includes any .emoji SVGs
+    // Check if the selection includes any .emoji SVGs (or images)
     let hasEmojiSvg = false;
     for (let i = 0; i < selection.rangeCount; i++) {
         const range = selection.getRangeAt(i);
         const clonedContent = range.cloneContents();
-        if (clonedContent.querySelector('svg.emoji')) {
+        if (clonedContent.querySelector('svg.emoji, img.emoji')) {
             hasEmojiSvg = true;
             break;
         }
@@ -15,17 +15,16 @@ document.addEventListener('copy', function(e) {
 
     if (!hasEmojiSvg) return; // No action needed if no relevant SVGs
 
-    // Process the selection to replace SVGs with alt text
+    // Process the selection to replace SVGs with their emoji
     const tempDiv = document.createElement('div');
     for (let i = 0; i < selection.rangeCount; i++) {
         const range = selection.getRangeAt(i);
         tempDiv.appendChild(range.cloneContents());
     }
 
-    // Replace each SVG with its alt text
-    tempDiv.querySelectorAll('svg.emoji').forEach(svg => {
-        const altText = svg.getAttribute('alt') || '';
-        svg.replaceWith(altText);
+    // Replace each SVG with the emoji character it stands for
+    tempDiv.querySelectorAll('svg.emoji, img.emoji').forEach(emoji => {
+        emoji.replaceWith(emoji.dataset.emoji || '');
     });
 
     // Update clipboard data
@@ -65,7 +65,7 @@ const debounce = (fn, delay) => {
   };
 };
 
-const createResultHTML = ({ path, title, matched }) => `
+const createResultHTML = ({ path, title, matched, reading_time }) => `
   <a href="/${path}" tabindex="0">
     <div class="search-result-container">
       <span class="search-result-title">${title}</span>
@@ -73,6 +73,7 @@ const createResultHTML = ({ path, title, matched }) => `
     </div>
     <div class="search-result-path-container">
       <span class="search-result-path">${path}/</span>
+      <span class="search-result-reading-time">${reading_time} min read</span>
     </div>
   </a>
 `;
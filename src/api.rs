@@ -1,52 +1,262 @@
-use std::path::{Path, PathBuf};
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::SystemTime,
+};
 
 use either::Either;
+use httpdate::HttpDate;
 use rocket::{
-    fs::NamedFile,
-    get,
-    response::content::RawHtml,
+    Request, Response, State, get,
+    http::{ContentType, Header, Method, Status},
+    request::{self, FromRequest, Outcome},
+    response::{self, Responder, content::RawHtml},
     serde::{Serialize, json::Json},
 };
 
-use crate::pages::{self, PAGE_CACHE_DIR};
+use crate::{
+    compression::{Compressed, Encoding, Representation},
+    config::SiteConfig,
+    content::{self, Content},
+    html::escape_html,
+    pages::Site,
+    tls_status::{TlsStatus, TlsStatusReport},
+};
+
+/// `Cache-Control` of fingerprinted files, see [`crate::assets`].
+const IMMUTABLE: &str = "public, max-age=31536000, immutable";
+
+/// Adds `Cache-Control`, `ETag` and `Last-Modified` headers to the wrapped response, or replaces
+/// it with a `304 Not Modified` when the client's copy is still current.
+pub struct Cached<R> {
+    response: R,
+    cache_control: Option<String>,
+    etag: Option<&'static str>,
+    last_modified: Option<SystemTime>,
+}
+
+impl<R> Cached<R> {
+    /// Checks the request's `If-None-Match`, or otherwise `If-Modified-Since`, header against the
+    /// response (RFC 9110 section 13.2.2).
+    fn is_not_modified(&self, request: &Request<'_>) -> bool {
+        if !matches!(request.method(), Method::Get | Method::Head) {
+            return false;
+        }
+
+        if let Some(if_none_match) = request.headers().get_one("If-None-Match") {
+            // Weak comparison, so `W/` prefixes added by proxies (e.g. when compressing) still match:
+            return self.etag.is_some_and(|etag| {
+                if_none_match.trim() == "*"
+                    || if_none_match
+                        .split(',')
+                        .any(|tag| tag.trim().trim_start_matches("W/") == etag)
+            });
+        }
+
+        let if_modified_since = request
+            .headers()
+            .get_one("If-Modified-Since")
+            .and_then(|date| date.parse::<HttpDate>().ok());
+        match (if_modified_since, self.last_modified) {
+            (Some(since), Some(last_modified)) => HttpDate::from(last_modified) <= since,
+            _ => false,
+        }
+    }
+}
+
+impl<'r, R: Responder<'r, 'static>> Responder<'r, 'static> for Cached<R> {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        let mut response = match self.is_not_modified(request) {
+            true => Response::build().status(Status::NotModified).finalize(),
+            false => self.response.respond_to(request)?,
+        };
+        if let Some(cache_control) = self.cache_control {
+            response.set_raw_header("Cache-Control", cache_control);
+        }
+        if let Some(etag) = self.etag {
+            response.set_raw_header("ETag", etag);
+        }
+        if let Some(last_modified) = self.last_modified {
+            response.set_raw_header("Last-Modified", httpdate::fmt_http_date(last_modified));
+        }
+        Ok(response)
+    }
+}
+
+/// Sets the `Content-Encoding` of the wrapped response, and the `Content-Type` of its
+/// uncompressed body.
+pub struct Encoded<R> {
+    response: R,
+    encoding: Option<Encoding>,
+    content_type: Option<ContentType>,
+}
+
+impl<'r, R: Responder<'r, 'static>> Responder<'r, 'static> for Encoded<R> {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        let mut response = self.response.respond_to(request)?;
+        if response.status() != Status::NotModified {
+            if let Some(content_type) = self.content_type {
+                response.set_header(content_type);
+            }
+            if let Some(encoding) = self.encoding {
+                response.set_raw_header("Content-Encoding", encoding.name());
+            }
+        }
+        // Caches have to keep each encoding apart:
+        response.set_raw_header("Vary", "Accept-Encoding");
+        Ok(response)
+    }
+}
+
+/// The encodings the client accepts, most preferred first, see [`Encoding::accepted`].
+pub struct AcceptEncoding(Vec<Encoding>);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for AcceptEncoding {
+    type Error = std::convert::Infallible;
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        let accept_encoding = request.headers().get_one("Accept-Encoding");
+        Outcome::Success(Self(
+            accept_encoding.map_or_else(Vec::new, Encoding::accepted),
+        ))
+    }
+}
 
 #[get("/<path..>")]
-pub async fn html_or_file(path: PathBuf) -> Option<Either<RawHtml<&'static str>, NamedFile>> {
-    if path.extension().is_some() {
-        NamedFile::open(Path::new(PAGE_CACHE_DIR).join(path))
-            .await
-            .ok()
-            .map(Either::Right)
+pub async fn html_or_file(
+    path: PathBuf,
+    accept_encoding: AcceptEncoding,
+    site: &State<Site>,
+    content: &State<Content>,
+    config: &State<SiteConfig>,
+) -> Option<Either<Encoded<Cached<&'static [u8]>>, Encoded<Cached<content::File>>>> {
+    if let Some(extension) = path.extension() {
+        let content_type = ContentType::from_extension(&extension.to_string_lossy());
+        // Pages rendered before a file changed (e.g. cached from before a deploy) link to an
+        // outdated fingerprinted URL, which gets the current contents, just not for good:
+        let (path, fingerprinted_cache_control) = match site.current_fingerprint(&path) {
+            Some(current) => (current, "no-cache"),
+            None => (path, IMMUTABLE),
+        };
+
+        // Fingerprinted stylesheets link to fingerprinted URLs themselves:
+        if let Some(&stylesheet) = site.fingerprints.stylesheet(&path) {
+            return Some(Either::Left(encoded(
+                stylesheet,
+                &accept_encoding,
+                ContentType::CSS,
+                Some(fingerprinted_cache_control.to_owned()),
+            )));
+        }
+
+        // The emoji sprite sheet is rendered rather than read from a file:
+        if let Some(sprite) = site
+            .emoji_sprite
+            .as_ref()
+            .filter(|sprite| path == sprite.path || path == sprite.original)
+        {
+            // Pages rendered before the sprite sheet was fingerprinted link to the original URL,
+            // which serves the latest one:
+            let cache_control = match path == sprite.path {
+                true => fingerprinted_cache_control,
+                false => "no-cache",
+            };
+            return Some(Either::Left(encoded(
+                sprite.svg,
+                &accept_encoding,
+                ContentType::SVG,
+                Some(cache_control.to_owned()),
+            )));
+        }
+
+        // A fingerprinted URL only ever refers to the same contents:
+        let (path, cache_control) = match site.fingerprints.original(&path) {
+            Some(original) => (
+                original.to_path_buf(),
+                Some(fingerprinted_cache_control.to_owned()),
+            ),
+            None => {
+                let cache_control = config.cache_control.for_file(&path).map(str::to_owned);
+                (path, cache_control)
+            }
+        };
+        let last_modified = content.dates(&path).ok().map(|(_, modified)| modified);
+
+        // Serve a precompressed copy (e.g. `main.js.br`) when there is one the client accepts,
+        // unless it is older than the file (which has been edited since it was compressed):
+        let mut precompressed = None;
+        for &encoding in &accept_encoding.0 {
+            let mut compressed_path = path.clone().into_os_string();
+            compressed_path.push(format!(".{}", encoding.extension()));
+            let is_current = content.dates(&compressed_path).is_ok_and(|(_, modified)| {
+                last_modified.is_none_or(|original| modified >= original)
+            });
+            if !is_current {
+                continue;
+            }
+            if let Ok(file) = content.open(compressed_path).await {
+                precompressed = Some((file, encoding));
+                break;
+            }
+        }
+        let (file, encoding) = match precompressed {
+            Some((file, encoding)) => (file, Some(encoding)),
+            None => (content.open(path).await.ok()?, None),
+        };
+
+        Some(Either::Right(Encoded {
+            response: Cached {
+                response: file,
+                cache_control,
+                etag: None,
+                last_modified,
+            },
+            encoding,
+            content_type,
+        }))
     } else {
-        pages::get_page_cache()
-            .get(path.as_path())
-            .cloned()
-            .map(RawHtml)
-            .map(Either::Left)
+        // No `Last-Modified`, as pages also change with the template, configuration and assets
+        // they are rendered with, which the ETag (a hash of the rendered HTML) covers:
+        let page = site.pages.get(&path)?;
+        Some(Either::Left(encoded(
+            page.html,
+            &accept_encoding,
+            ContentType::HTML,
+            config.cache_control.pages.clone(),
+        )))
+    }
+}
+
+/// Serves `representation` in the encoding the client prefers, with the matching `ETag`.
+fn encoded(
+    representation: Representation,
+    accept_encoding: &AcceptEncoding,
+    content_type: ContentType,
+    cache_control: Option<String>,
+) -> Encoded<Cached<&'static [u8]>> {
+    let compressed = Compressed::pick(representation.compressed, &accept_encoding.0);
+    Encoded {
+        response: Cached {
+            response: compressed
+                .map_or(representation.body.as_bytes(), |compressed| compressed.body),
+            cache_control,
+            etag: Some(compressed.map_or(representation.etag, |compressed| compressed.etag)),
+            last_modified: None,
+        },
+        encoding: compressed.map(|compressed| compressed.encoding),
+        content_type: Some(content_type),
     }
 }
 
 #[derive(Serialize)]
 #[serde(crate = "rocket::serde")]
 pub struct QueryMatch {
-    title: &'static str,
+    title: String,
     path: String,
     matched: String,
-}
-
-fn escape_html(s: &str) -> String {
-    let mut escaped = String::with_capacity(s.len());
-    for c in s.chars() {
-        match c {
-            '&' => escaped.push_str("&amp;"),
-            '<' => escaped.push_str("&lt;"),
-            '>' => escaped.push_str("&gt;"),
-            '"' => escaped.push_str("&quot;"),
-            '\'' => escaped.push_str("&apos;"),
-            _ => escaped.push(c),
-        }
-    }
-    escaped
+    word_count: usize,
+    reading_time: usize,
 }
 
 fn get_match_context(content: &str, query: &str) -> String {
@@ -71,11 +281,13 @@ fn get_match_context(content: &str, query: &str) -> String {
 }
 
 #[get("/search?<query>")]
-pub async fn search(query: &str) -> Json<Vec<QueryMatch>> {
-    let query_matches = pages::get_page_cache()
-        .into_iter()
+pub async fn search(query: &str, site: &State<Site>) -> Json<Vec<QueryMatch>> {
+    let query_matches = site
+        .pages
+        .iter()
         .filter(|(path, _)| path.file_name() != Some("index".as_ref()))
-        .filter_map(|(path, html)| {
+        .filter_map(|(path, page)| {
+            let html = page.html.body;
             let path_str = path.to_string_lossy();
             let html_contains = html.contains(query);
             let path_contains = path_str.contains(query);
@@ -84,13 +296,8 @@ pub async fn search(query: &str) -> Json<Vec<QueryMatch>> {
                 return None;
             }
 
-            let title = html
-                .find("<title>")
-                .and_then(|i| {
-                    html[i..]
-                        .find("</title>")
-                        .map(|j| html[i + 7..i + j].trim())
-                })
+            let title = page
+                .title
                 .unwrap_or_else(|| path.to_str().unwrap_or("Untitled"));
 
             let matched = if html_contains {
@@ -100,9 +307,11 @@ pub async fn search(query: &str) -> Json<Vec<QueryMatch>> {
             };
 
             Some(QueryMatch {
-                title,
+                title: title.to_owned(),
                 path: path_str.to_string(),
                 matched,
+                word_count: page.word_count,
+                reading_time: page.reading_time,
             })
         })
         .collect();
@@ -110,11 +319,75 @@ pub async fn search(query: &str) -> Json<Vec<QueryMatch>> {
     Json(query_matches)
 }
 
+/// Request guard for the `/_status` endpoints, which require an
+/// `Authorization: Bearer <status_token>` header.
+pub struct StatusAuthorization;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for StatusAuthorization {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, ()> {
+        let expected = request
+            .rocket()
+            .state::<SiteConfig>()
+            .and_then(SiteConfig::status_token);
+        let given = request
+            .headers()
+            .get_one("Authorization")
+            .and_then(|authorization| authorization.strip_prefix("Bearer "));
+
+        match (expected, given) {
+            (Some(expected), Some(given)) if constant_time_eq(expected, given) => {
+                Outcome::Success(Self)
+            }
+            _ => Outcome::Error((Status::Unauthorized, ())),
+        }
+    }
+}
+
+/// The `401 Unauthorized` of the `/_status` endpoints, naming the scheme they expect as RFC 9110
+/// section 11.6.1 requires.
+#[derive(Responder)]
+#[response(status = 401)]
+pub struct Unauthorized(&'static str, Header<'static>);
+
+#[catch(401)]
+pub fn unauthorized() -> Unauthorized {
+    Unauthorized(
+        "401 - Unauthorized",
+        Header::new("WWW-Authenticate", "Bearer"),
+    )
+}
+
+/// Compares two strings without returning early, so the time taken doesn't leak the token.
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0, |difference, (a, b)| difference | (a ^ b))
+            == 0
+}
+
+#[get("/_status/tls")]
+pub fn tls_status(
+    _authorization: StatusAuthorization,
+    tls_status: &State<Arc<TlsStatus>>,
+) -> Json<TlsStatusReport> {
+    Json(tls_status.report())
+}
+
+#[get("/_status/metrics")]
+pub fn metrics(_authorization: StatusAuthorization, tls_status: &State<Arc<TlsStatus>>) -> String {
+    tls_status.report().to_prometheus()
+}
+
 #[catch(404)]
-pub fn not_found() -> RawHtml<&'static str> {
-    pages::get_page_cache()
-        .get(Path::new("404"))
-        .cloned()
-        .map(RawHtml)
+pub fn not_found(request: &Request<'_>) -> RawHtml<&'static str> {
+    request
+        .rocket()
+        .state::<Site>()
+        .and_then(|site| site.pages.get(Path::new("404")))
+        .map(|page| RawHtml(page.html.body))
         .unwrap_or_else(|| RawHtml("404 - Page not found"))
 }
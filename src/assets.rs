@@ -0,0 +1,199 @@
+//! 🔖 Content-hashed ("fingerprinted") URLs for static files, which always refer to the same
+//! contents and so can be cached forever.
+
+use std::{
+    collections::HashMap,
+    ffi::OsStr,
+    io::{Error, ErrorKind, Result},
+    path::{Path, PathBuf},
+};
+
+use aho_corasick::{AhoCorasick, MatchKind};
+
+use crate::{
+    compression::{Encoding, Representation},
+    content::Content,
+    pages::content_hash,
+};
+
+/// Hex digits of the content hash put in fingerprinted file names.
+const FINGERPRINT_LENGTH: usize = 16;
+
+/// The fingerprinted path of every static file referenced by the pages, e.g.
+/// `templates/template.0123456789abcdef.css` for `templates/template.css`.
+///
+/// Files are only hashed once a page (or stylesheet) links to them, see [`Fingerprints::rewrite`].
+pub struct Fingerprints {
+    /// Every static file that can be fingerprinted.
+    paths: Vec<PathBuf>,
+    /// Finds quoted absolute URLs of the files (e.g. `"/templates/template.css"`), the pattern IDs
+    /// are indices into `paths`.
+    urls: AhoCorasick,
+    /// Quoted fingerprinted URLs of the files hashed so far by index into `paths`, `None` while a
+    /// stylesheet's own references are being rewritten.
+    fingerprinted_urls: HashMap<usize, Option<String>>,
+    /// Fingerprinted paths to the files they were made from.
+    originals: HashMap<PathBuf, Asset>,
+}
+
+/// `path` with the content hash of `body` before its extension, e.g.
+/// `templates/template.0123456789abcdef.css` for `templates/template.css`.
+pub fn fingerprinted_path(path: &Path, body: &[u8]) -> PathBuf {
+    let extension = path.extension().and_then(OsStr::to_str).unwrap_or_default();
+    let hash = content_hash(body);
+    path.with_extension(format!("{}.{extension}", &hash[..FINGERPRINT_LENGTH]))
+}
+
+/// `path` without the content hash [`fingerprinted_path`] put in it, `None` if it has none.
+pub fn unfingerprinted(path: &Path) -> Option<PathBuf> {
+    let extension = path.extension()?.to_str()?;
+    let (stem, hash) = path.file_stem()?.to_str()?.rsplit_once('.')?;
+    let is_hash =
+        hash.len() == FINGERPRINT_LENGTH && hash.bytes().all(|byte| byte.is_ascii_hexdigit());
+    is_hash.then(|| path.with_file_name(format!("{stem}.{extension}")))
+}
+
+/// A fingerprinted file.
+struct Asset {
+    original: PathBuf,
+    /// Served instead of the file for stylesheets, as their URLs are rewritten too.
+    stylesheet: Option<Representation>,
+}
+
+impl Fingerprints {
+    /// Finds every file in `content` that can be fingerprinted, which is all of them except the
+    /// markdown pages and precompressed copies.
+    pub fn new(content: &Content) -> Result<Self> {
+        let paths = content
+            .read_dir_all("")?
+            .into_iter()
+            .filter(|path| {
+                let Some(extension) = path.extension().and_then(OsStr::to_str) else {
+                    return false;
+                };
+                let is_precompressed = Encoding::ALL
+                    .iter()
+                    .any(|encoding| encoding.extension() == extension);
+                extension != "md" && !is_precompressed
+            })
+            .collect();
+
+        Self::build(paths)
+    }
+
+    /// No fingerprints, URLs are left as they are.
+    pub fn none() -> Result<Self> {
+        Self::build(Vec::new())
+    }
+
+    fn build(paths: Vec<PathBuf>) -> Result<Self> {
+        let urls = paths.iter().map(|path| format!("\"/{}\"", path.display()));
+        Ok(Self {
+            urls: AhoCorasick::builder()
+                .match_kind(MatchKind::LeftmostLongest)
+                .build(urls)
+                .map_err(Error::other)?,
+            paths,
+            fingerprinted_urls: HashMap::new(),
+            originals: HashMap::new(),
+        })
+    }
+
+    /// Replaces the quoted URLs of static files in `html` (in attributes like `href="/main.js"` or
+    /// CSS like `url("/font.woff2")`) with their fingerprinted URLs, hashing the files the first
+    /// time they are linked to.
+    ///
+    /// Stylesheets get the same treatment before they are hashed, so their fingerprint changes
+    /// with the files they reference; a stylesheet that (indirectly) references itself keeps the
+    /// original URL for that reference.
+    pub fn rewrite(&mut self, content: &Content, html: &str) -> Result<String> {
+        let matches: Vec<_> = self
+            .urls
+            .find_iter(html)
+            .map(|found| (found.range(), found.pattern().as_usize()))
+            .collect();
+
+        let mut rewritten = String::with_capacity(html.len());
+        let mut last_end = 0;
+        for (range, index) in matches {
+            rewritten.push_str(&html[last_end..range.start]);
+            match self.fingerprint(content, index)? {
+                Some(url) => rewritten.push_str(&url),
+                None => rewritten.push_str(&html[range.clone()]),
+            }
+            last_end = range.end;
+        }
+        rewritten.push_str(&html[last_end..]);
+
+        Ok(rewritten)
+    }
+
+    /// The quoted fingerprinted URL of `paths[index]`, `None` while it is being fingerprinted.
+    fn fingerprint(&mut self, content: &Content, index: usize) -> Result<Option<String>> {
+        if let Some(url) = self.fingerprinted_urls.get(&index) {
+            return Ok(url.clone());
+        }
+        self.fingerprinted_urls.insert(index, None);
+
+        let original = self.paths[index].clone();
+        let extension = original
+            .extension()
+            .and_then(OsStr::to_str)
+            .unwrap_or_default();
+        let mut body = content.read(&original)?;
+        let is_stylesheet = extension == "css";
+        if is_stylesheet {
+            let css = String::from_utf8(body).map_err(|error| {
+                Error::new(
+                    ErrorKind::InvalidData,
+                    format!("{}: {error}", original.display()),
+                )
+            })?;
+            body = self.rewrite(content, &css)?.into_bytes();
+        }
+
+        let fingerprinted = fingerprinted_path(&original, &body);
+        let url = format!("\"/{}\"", fingerprinted.display());
+        let stylesheet = match is_stylesheet {
+            true => Some(Representation::new(String::from_utf8(body).unwrap())?),
+            false => None,
+        };
+
+        self.originals.insert(
+            fingerprinted,
+            Asset {
+                original,
+                stylesheet,
+            },
+        );
+        self.fingerprinted_urls.insert(index, Some(url.clone()));
+        Ok(Some(url))
+    }
+
+    /// The file a fingerprinted path was made from.
+    pub fn original(&self, fingerprinted: &Path) -> Option<&Path> {
+        self.originals
+            .get(fingerprinted)
+            .map(|asset| asset.original.as_path())
+    }
+
+    /// The current fingerprinted path of a file, `None` if no page links to it.
+    pub fn fingerprinted(&self, original: &Path) -> Option<&Path> {
+        self.originals
+            .iter()
+            .find(|(_, asset)| asset.original == original)
+            .map(|(fingerprinted, _)| fingerprinted.as_path())
+    }
+
+    /// The rewritten stylesheet to serve for a fingerprinted path, `None` for other files.
+    pub fn stylesheet(&self, fingerprinted: &Path) -> Option<&Representation> {
+        self.originals.get(fingerprinted)?.stylesheet.as_ref()
+    }
+
+    /// Every `(fingerprinted, original)` path.
+    pub fn iter(&self) -> impl Iterator<Item = (&Path, &Path)> {
+        self.originals
+            .iter()
+            .map(|(fingerprinted, asset)| (fingerprinted.as_path(), asset.original.as_path()))
+    }
+}
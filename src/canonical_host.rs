@@ -0,0 +1,145 @@
+//! 🏷️ Redirects requests for alternate domains (e.g. `www.auxv.org`) to the canonical one.
+
+use rocket::{
+    Data, Request, Response,
+    fairing::{Fairing, Info, Kind},
+    http::{Method, Status, uri::Origin},
+    request::{FromRequest, Outcome},
+    response::{self, Responder},
+};
+
+/// Where requests for alternate domains are rewritten to, so they never reach the other routes.
+const REDIRECT_PATH: &str = "/_canonical_host";
+
+/// A fairing that rewrites any request made for one of the site's alternate domains to the
+/// [`redirect`] route, which answers it with a permanent redirect to the same path and query on
+/// the canonical domain.
+///
+/// Requests without a `Host` header, or for unknown hosts, are left alone.
+///
+/// # Example
+/// ```rust
+/// # use auxv_dot_org::canonical_host::{self, CanonicalHost};
+/// let rocket = rocket::build()
+///     .attach(CanonicalHost::new(["example.com", "www.example.com"], 443))
+///     .mount("/", rocket::routes![canonical_host::redirect]);
+/// ```
+pub struct CanonicalHost {
+    canonical: CanonicalDomain,
+}
+
+impl CanonicalHost {
+    pub fn new(domains: impl IntoIterator<Item = impl Into<String>>, https_port: u16) -> Self {
+        Self {
+            canonical: CanonicalDomain::new(domains, https_port),
+        }
+    }
+}
+
+/// The site's domains and HTTPS port, which permanent redirects to the canonical domain are made
+/// from. Shared with the plain HTTP redirect.
+#[derive(Clone, Debug)]
+pub struct CanonicalDomain {
+    /// The site's domains, the first one is the redirect target.
+    domains: Vec<String>,
+    https_port: u16,
+}
+
+impl CanonicalDomain {
+    pub fn new(domains: impl IntoIterator<Item = impl Into<String>>, https_port: u16) -> Self {
+        let domains: Vec<String> = domains.into_iter().map(Into::into).collect();
+        assert!(
+            !domains.is_empty(),
+            "At least one domain is required to redirect to"
+        );
+        Self {
+            domains,
+            https_port,
+        }
+    }
+
+    /// Every domain of the site, the canonical one first.
+    pub fn domains(&self) -> &[String] {
+        &self.domains
+    }
+
+    /// The status and `Location` redirecting a `method` request for `target` (its path and query)
+    /// to the same target over HTTPS on the canonical domain.
+    pub fn redirect(&self, method: &str, target: &str) -> (Status, String) {
+        let port = match self.https_port {
+            443 => String::new(),
+            port => format!(":{port}"),
+        };
+        // 308 keeps the method and body, which matters for anything but GET or HEAD:
+        let status = match method {
+            "GET" | "HEAD" => Status::MovedPermanently,
+            _ => Status::PermanentRedirect,
+        };
+        (status, format!("https://{}{port}{target}", self.domains[0]))
+    }
+}
+
+#[rocket::async_trait]
+impl Fairing for CanonicalHost {
+    fn info(&self) -> Info {
+        Info {
+            name: "Canonical Host",
+            kind: Kind::Request,
+        }
+    }
+
+    async fn on_request(&self, request: &mut Request<'_>, _data: &mut Data<'_>) {
+        let Some(host) = request.host() else {
+            return;
+        };
+        let is_alternate = self.canonical.domains()[1..]
+            .iter()
+            .any(|domain| host.domain() == domain.as_str());
+        if !is_alternate {
+            return;
+        }
+
+        let (status, location) = self
+            .canonical
+            .redirect(request.method().as_str(), &request.uri().to_string());
+
+        request.local_cache(|| Some(CanonicalRedirect { status, location }));
+        request.set_method(Method::Get);
+        request.set_uri(Origin::parse(REDIRECT_PATH).unwrap());
+    }
+}
+
+/// The redirect [`CanonicalHost`] decided on for a request.
+#[derive(Clone)]
+pub struct CanonicalRedirect {
+    status: Status,
+    location: String,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for &'r CanonicalRedirect {
+    type Error = ();
+
+    /// Only succeeds for requests rewritten by [`CanonicalHost`].
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        match request.local_cache(|| None::<CanonicalRedirect>) {
+            Some(redirect) => Outcome::Success(redirect),
+            None => Outcome::Forward(Status::NotFound),
+        }
+    }
+}
+
+impl<'r> Responder<'r, 'static> for CanonicalRedirect {
+    fn respond_to(self, _request: &'r Request<'_>) -> response::Result<'static> {
+        Response::build()
+            .status(self.status)
+            .raw_header("Location", self.location)
+            .ok()
+    }
+}
+
+/// Answers the requests [`CanonicalHost`] rewrote.
+#[get("/_canonical_host")]
+pub fn redirect(redirect: &CanonicalRedirect) -> CanonicalRedirect {
+    redirect.clone()
+}
@@ -0,0 +1,282 @@
+use std::{
+    fmt::Display,
+    io::{Error, ErrorKind, Result},
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime},
+};
+
+use auxv_dot_org::tls_status::TlsStatus;
+use rocket::listener::{Connection, Endpoint, Listener};
+use tokio::{
+    signal::unix::{Signal, SignalKind, signal},
+    time::interval,
+};
+use tokio_rustls::{
+    TlsAcceptor,
+    rustls::{
+        Error as TlsError, InconsistentKeys, ServerConfig,
+        crypto::{CryptoProvider, ring},
+        pki_types::{CertificateDer, PrivateKeyDer, pem::PemObject},
+        server::{ClientHello, ResolvesServerCert},
+        sign::CertifiedKey,
+    },
+};
+
+use crate::{
+    sockets::{Socket, Sockets},
+    tls::{ALPN_PROTOCOLS, TlsConnection, certificate_expiry, complete_handshake},
+};
+
+/// How often the certificate files are checked for changes.
+const RELOAD_INTERVAL: Duration = Duration::from_secs(60);
+
+/// 📜 A Rocket-compatible HTTPS listener that serves a certificate and key from PEM files.
+///
+/// This is an alternative to the [`LetsEncryptListener`](crate::lets_encrypt_listener) for
+/// environments without ACME, e.g. with a self-signed or internally issued certificate. The
+/// files are reloaded when either of them changes, or when the process receives `SIGHUP`; if
+/// they fail to load, or the key doesn't belong to the certificate, the previous certificate is
+/// kept. The expiry of the certificate being served is recorded in the [`TlsStatus`].
+///
+/// # Example
+/// ```rust
+/// let unix_permissions = UnixPermissions::default();
+/// let sockets = Sockets::bind(&["0.0.0.0".parse()?], 443, "https", &unix_permissions)?;
+/// let https_listener = CertificateFilesListener::new("cert.pem", "key.pem", sockets, tls_status)?;
+/// rocket.launch_on(https_listener).await?;
+/// ```
+pub struct CertificateFilesListener {
+    sockets: Sockets,
+    tls_acceptor: TlsAcceptor,
+}
+
+impl CertificateFilesListener {
+    /// Loads the certificate chain and private key, and starts watching them for changes.
+    pub fn new(
+        cert_path: impl Into<PathBuf>,
+        key_path: impl Into<PathBuf>,
+        sockets: Sockets,
+        tls_status: Arc<TlsStatus>,
+    ) -> Result<Self> {
+        let provider = Arc::new(ring::default_provider());
+        let resolver = Arc::new(CertificateFiles::load(
+            cert_path.into(),
+            key_path.into(),
+            provider.clone(),
+            tls_status,
+        )?);
+        let hangups = signal(SignalKind::hangup())?;
+        tokio::spawn(resolver.clone().watch(hangups));
+
+        let mut server_config = ServerConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()
+            .map_err(Error::other)?
+            .with_no_client_auth()
+            .with_cert_resolver(resolver);
+        server_config.alpn_protocols = ALPN_PROTOCOLS.map(|protocol| protocol.to_vec()).to_vec();
+
+        Ok(Self {
+            sockets,
+            tls_acceptor: TlsAcceptor::from(Arc::new(server_config)),
+        })
+    }
+}
+
+impl Listener for CertificateFilesListener {
+    type Accept = Socket;
+
+    type Connection = TlsConnection;
+
+    async fn accept(&self) -> Result<Self::Accept> {
+        self.sockets.accept().await
+    }
+
+    /// Completes the TLS handshake, failures only drop this connection.
+    async fn connect(&self, socket: Self::Accept) -> Result<Self::Connection> {
+        let remote = socket.endpoint()?;
+        complete_handshake(remote, self.tls_acceptor.accept(socket)).await
+    }
+
+    fn endpoint(&self) -> Result<Endpoint> {
+        self.sockets.endpoint()
+    }
+}
+
+/// Resolves every TLS handshake to the certificate most recently loaded from the PEM files.
+#[derive(Debug)]
+struct CertificateFiles {
+    cert_path: PathBuf,
+    key_path: PathBuf,
+    provider: Arc<CryptoProvider>,
+    tls_status: Arc<TlsStatus>,
+    certified_key: RwLock<Arc<CertifiedKey>>,
+}
+
+impl CertificateFiles {
+    fn load(
+        cert_path: PathBuf,
+        key_path: PathBuf,
+        provider: Arc<CryptoProvider>,
+        tls_status: Arc<TlsStatus>,
+    ) -> Result<Self> {
+        let certified_key = load_certified_key(&cert_path, &key_path, &provider)?;
+        record_expiry(&tls_status, &certified_key);
+        Ok(Self {
+            cert_path,
+            key_path,
+            provider,
+            tls_status,
+            certified_key: RwLock::new(Arc::new(certified_key)),
+        })
+    }
+
+    fn reload(&self) {
+        match load_certified_key(&self.cert_path, &self.key_path, &self.provider) {
+            Ok(certified_key) => {
+                record_expiry(&self.tls_status, &certified_key);
+                *self.certified_key.write().unwrap() = Arc::new(certified_key);
+                log::info!("Reloaded TLS certificate from {}", self.cert_path.display());
+            }
+            Err(error) => {
+                log::error!("Failed to reload TLS certificate, keeping the previous one: {error}")
+            }
+        }
+    }
+
+    /// Reloads the files on `SIGHUP`, or when their modification times change.
+    async fn watch(self: Arc<Self>, mut hangups: Signal) {
+        let mut ticks = interval(RELOAD_INTERVAL);
+        let mut last_modified = self.modified();
+
+        loop {
+            tokio::select! {
+                _ = hangups.recv() => self.reload(),
+                _ = ticks.tick() => {
+                    let modified = self.modified();
+                    if modified != last_modified {
+                        self.reload();
+                    }
+                    last_modified = modified;
+                }
+            }
+        }
+    }
+
+    fn modified(&self) -> [Option<SystemTime>; 2] {
+        [&self.cert_path, &self.key_path].map(|path| {
+            std::fs::metadata(path)
+                .and_then(|metadata| metadata.modified())
+                .ok()
+        })
+    }
+}
+
+impl ResolvesServerCert for CertificateFiles {
+    fn resolve(&self, _client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(self.certified_key.read().unwrap().clone())
+    }
+}
+
+fn load_certified_key(
+    cert_path: &Path,
+    key_path: &Path,
+    provider: &CryptoProvider,
+) -> Result<CertifiedKey> {
+    let cert_chain = CertificateDer::pem_file_iter(cert_path)
+        .and_then(|certs| certs.collect::<std::result::Result<Vec<_>, _>>())
+        .map_err(|error| invalid_data(cert_path, error))?;
+    if cert_chain.is_empty() {
+        return Err(invalid_data(cert_path, "no certificates found"));
+    }
+
+    let key =
+        PrivateKeyDer::from_pem_file(key_path).map_err(|error| invalid_data(key_path, error))?;
+    let signing_key = provider
+        .key_provider
+        .load_private_key(key)
+        .map_err(|error| invalid_data(key_path, error))?;
+
+    let certified_key = CertifiedKey::new(cert_chain, signing_key);
+    // Keys that can't report their public half get the benefit of the doubt, as rustls does:
+    match certified_key.keys_match() {
+        Ok(()) | Err(TlsError::InconsistentKeys(InconsistentKeys::Unknown)) => Ok(certified_key),
+        Err(error) => Err(invalid_data(key_path, error)),
+    }
+}
+
+fn record_expiry(tls_status: &TlsStatus, certified_key: &CertifiedKey) {
+    if let Some(expiry) = certificate_expiry(certified_key) {
+        tls_status.set_certificate_expiry(expiry);
+    }
+}
+
+fn invalid_data(path: &Path, error: impl Display) -> Error {
+    Error::new(
+        ErrorKind::InvalidData,
+        format!("{}: {error}", path.display()),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures/certificate_files")
+            .join(name)
+    }
+
+    fn load(cert_path: PathBuf, key_path: PathBuf) -> Result<CertificateFiles> {
+        CertificateFiles::load(
+            cert_path,
+            key_path,
+            Arc::new(ring::default_provider()),
+            Arc::default(),
+        )
+    }
+
+    #[test]
+    fn matching_files_load() {
+        let files = load(fixture("cert.pem"), fixture("key.pem")).unwrap();
+        assert!(files.tls_status.report().certificate_expires_at.is_some());
+    }
+
+    #[test]
+    fn mismatched_files_are_rejected() {
+        let error = load(fixture("cert.pem"), fixture("other_key.pem")).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn reloads_keep_the_previous_certificate_on_a_mismatch() {
+        let directory = tempfile::tempdir().unwrap();
+        let cert_path = directory.path().join("cert.pem");
+        let key_path = directory.path().join("key.pem");
+        fs::copy(fixture("cert.pem"), &cert_path).unwrap();
+        fs::copy(fixture("key.pem"), &key_path).unwrap();
+
+        let files = load(cert_path.clone(), key_path.clone()).unwrap();
+        let original = files.certified_key.read().unwrap().clone();
+
+        // Only the certificate has been replaced so far, as if caught halfway through a renewal:
+        fs::copy(fixture("other_cert.pem"), &cert_path).unwrap();
+        files.reload();
+        assert!(Arc::ptr_eq(&original, &files.certified_key.read().unwrap()));
+
+        fs::copy(fixture("other_key.pem"), &key_path).unwrap();
+        files.reload();
+        let reloaded = files.certified_key.read().unwrap().clone();
+        assert!(!Arc::ptr_eq(&original, &reloaded));
+        assert_eq!(
+            reloaded.cert,
+            CertificateDer::pem_file_iter(fixture("other_cert.pem"))
+                .unwrap()
+                .collect::<std::result::Result<Vec<_>, _>>()
+                .unwrap()
+        );
+    }
+}
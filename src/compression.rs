@@ -0,0 +1,163 @@
+//! 🗜️ Compressed response bodies, negotiated through the request's `Accept-Encoding` header.
+
+use std::io::{Result, Write};
+
+use flate2::{Compression, write::GzEncoder};
+
+use crate::pages::content_hash;
+
+/// A response body rendered ahead of time, along with its `ETag` and compressed copies.
+#[derive(Clone, Copy)]
+pub struct Representation {
+    pub body: &'static str,
+    /// Quoted hash of the body, sent as its `ETag`.
+    pub etag: &'static str,
+    /// The body in every encoding.
+    pub compressed: &'static [Compressed],
+}
+
+impl Representation {
+    /// Hashes and compresses `body`, which is leaked as it's served until the process exits.
+    pub fn new(body: String) -> Result<Self> {
+        let hash = content_hash(body.as_bytes());
+        Ok(Self {
+            etag: format!("\"{hash}\"").leak(),
+            compressed: Compressed::all(body.as_bytes(), &hash)?,
+            body: body.leak(),
+        })
+    }
+}
+
+/// A response body compressed ahead of time in one `Content-Encoding`.
+pub struct Compressed {
+    pub encoding: Encoding,
+    pub body: &'static [u8],
+    /// The uncompressed body's `ETag` with the encoding appended, as each encoding is a different
+    /// representation.
+    pub etag: &'static str,
+}
+
+impl Compressed {
+    /// Compresses `body`, whose content hash is `hash`, in every [`Encoding`].
+    pub fn all(body: &[u8], hash: &str) -> Result<&'static [Self]> {
+        let compressed = Encoding::ALL
+            .into_iter()
+            .map(|encoding| {
+                Ok(Self {
+                    encoding,
+                    body: encoding.compress(body)?.leak(),
+                    etag: format!("\"{hash}-{}\"", encoding.name()).leak(),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(compressed.leak())
+    }
+
+    /// The most preferred of the `accepted` encodings available in `compressed`.
+    pub fn pick(compressed: &'static [Self], accepted: &[Encoding]) -> Option<&'static Self> {
+        accepted.iter().find_map(|&encoding| {
+            compressed
+                .iter()
+                .find(|compressed| compressed.encoding == encoding)
+        })
+    }
+}
+
+/// A `Content-Encoding` pages and files can be served in.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Encoding {
+    Brotli,
+    Zstd,
+    Gzip,
+}
+
+impl Encoding {
+    /// Every encoding, in order of preference when a client accepts several equally (smallest
+    /// output first).
+    pub const ALL: [Self; 3] = [Self::Brotli, Self::Zstd, Self::Gzip];
+
+    /// The name used in `Accept-Encoding` and `Content-Encoding` headers.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Brotli => "br",
+            Self::Zstd => "zstd",
+            Self::Gzip => "gzip",
+        }
+    }
+
+    /// The file extension of precompressed copies, e.g. `main.js.br`.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Brotli => "br",
+            Self::Zstd => "zst",
+            Self::Gzip => "gz",
+        }
+    }
+
+    /// Compresses `bytes` at the highest level, it's only done once per page. Debug builds (and
+    /// so tests) use the fastest level instead, as every page is compressed on startup.
+    pub fn compress(self, bytes: &[u8]) -> Result<Vec<u8>> {
+        let best = !cfg!(debug_assertions);
+        match self {
+            Self::Brotli => {
+                let quality = if best { 11 } else { 1 };
+                let mut writer = brotli::CompressorWriter::new(Vec::new(), 4096, quality, 22);
+                writer.write_all(bytes)?;
+                Ok(writer.into_inner())
+            }
+            Self::Zstd => zstd::encode_all(bytes, if best { 19 } else { 1 }),
+            Self::Gzip => {
+                let level = if best {
+                    Compression::best()
+                } else {
+                    Compression::fast()
+                };
+                let mut encoder = GzEncoder::new(Vec::new(), level);
+                encoder.write_all(bytes)?;
+                encoder.finish()
+            }
+        }
+    }
+
+    /// The encodings an `Accept-Encoding` header allows, most preferred first.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use auxv_dot_org::compression::Encoding;
+    /// assert_eq!(
+    ///     Encoding::accepted("gzip, br;q=0.5, *;q=0.1"),
+    ///     [Encoding::Gzip, Encoding::Brotli, Encoding::Zstd]
+    /// );
+    /// ```
+    pub fn accepted(accept_encoding: &str) -> Vec<Self> {
+        let codings: Vec<(&str, f32)> = accept_encoding
+            .split(',')
+            .filter_map(|coding| {
+                let mut parameters = coding.split(';').map(str::trim);
+                let name = parameters.next().filter(|name| !name.is_empty())?;
+                let quality = parameters
+                    .find_map(|parameter| parameter.strip_prefix("q="))
+                    .map_or(Some(1.0), |quality| quality.parse().ok())?;
+                Some((name, quality))
+            })
+            .collect();
+        let quality_of = |name: &str| {
+            codings
+                .iter()
+                .find(|(coding, _)| coding.eq_ignore_ascii_case(name))
+                .map(|&(_, quality)| quality)
+        };
+
+        let mut accepted: Vec<(Self, f32)> = Self::ALL
+            .into_iter()
+            .map(|encoding| {
+                let quality = quality_of(encoding.name()).or_else(|| quality_of("*"));
+                (encoding, quality.unwrap_or(0.0))
+            })
+            .filter(|&(_, quality)| quality > 0.0)
+            .collect();
+        // Stable, so equally accepted encodings stay in order of preference:
+        accepted.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+        accepted.into_iter().map(|(encoding, _)| encoding).collect()
+    }
+}
@@ -0,0 +1,307 @@
+//! ⚙️ Site configuration, loaded from `site.toml` with `AUXV_` environment variable overrides.
+//!
+//! Every key is optional and falls back to the [`Default`] implementations below, e.g.:
+//! ```toml
+//! domains = ["auxv.org"]
+//! email = "me@auxv.org"
+//! bind = ["0.0.0.0", "::", "unix:/run/auxv"]
+//! unix_socket_group = "www-data"
+//! acme_challenge = "http-01"
+//!
+//! [tls]  # Serve these PEM files instead of requesting a certificate from Let's Encrypt
+//! cert = "/etc/ssl/auxv.org/fullchain.pem"
+//! key = "/etc/ssl/auxv.org/privkey.pem"
+//!
+//! [render]
+//! dates = "git"
+//! emoji_mode = "sprite"
+//!
+//! [metadata]
+//! author = "Owen Friedman"
+//!
+//! [cache_control]
+//! pages = "no-cache"  # Revalidated with the page's ETag every time
+//! fonts = "public, max-age=2592000"
+//! files = "public, max-age=86400"
+//! ```
+//! Nested keys are overridden from the environment with `__` as the separator, e.g.
+//! `AUXV_RENDER__DATES=git`.
+
+use std::{
+    net::{IpAddr, Ipv4Addr},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use rocket::{
+    figment::{
+        self, Figment,
+        providers::{Env, Format, Toml},
+    },
+    http::ContentType,
+    serde::Deserialize,
+};
+
+use crate::{content::Content, emojis::EmojiMode, pages::PageDates};
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(crate = "rocket::serde", default)]
+pub struct SiteConfig {
+    /// Domains served over HTTPS with a single certificate, the first one is the canonical domain
+    /// and requests for the others are redirected to it.
+    pub domains: Vec<String>,
+    /// Let's Encrypt contact email.
+    pub email: Option<String>,
+    /// Where the HTTP and HTTPS listeners are bound, see [`BindAddress`].
+    pub bind: Vec<BindAddress>,
+    /// Permission bits of the Unix sockets in `bind`, e.g. `0o660` (TOML allows octal).
+    pub unix_socket_mode: u32,
+    /// Group to hand the Unix sockets in `bind` to, e.g. the reverse proxy's.
+    pub unix_socket_group: Option<String>,
+    pub http_port: u16,
+    pub https_port: u16,
+    /// Serve the site over plain HTTP only (for testing without Let's Encrypt).
+    pub http_only: bool,
+    /// Unprivileged user to switch to once the ports are bound.
+    pub user: Option<String>,
+    /// Group to switch to along with `user`, defaults to the user's primary group.
+    pub group: Option<String>,
+    pub lets_encrypt_cache: PathBuf,
+    /// Use the Let's Encrypt staging environment, which has far higher rate limits but issues
+    /// untrusted certificates.
+    pub lets_encrypt_staging: bool,
+    /// Directory URL of another ACME server to use instead of Let's Encrypt (e.g. a local Pebble
+    /// instance at `https://localhost:14000/dir`).
+    pub acme_directory: Option<String>,
+    /// PEM root certificate to trust when connecting to the ACME server, for test servers with
+    /// their own CA.
+    pub acme_root_certificate: Option<PathBuf>,
+    pub acme_challenge: AcmeChallenge,
+    /// Bearer token for the `/_status` endpoints, which aren't mounted when unset or empty (best
+    /// set through `AUXV_STATUS_TOKEN` rather than the configuration file).
+    pub status_token: Option<String>,
+    /// Certificate files to serve instead of using Let's Encrypt.
+    pub tls: Option<TlsFiles>,
+    /// Directory containing the site's pages, see [`SiteConfig::content`].
+    pub pages_dir: Option<PathBuf>,
+    pub features: Features,
+    pub render: Render,
+    pub metadata: Metadata,
+    pub cache_control: CacheControl,
+}
+
+/// An address to listen on, parsed from an IP address or `unix:<directory>`.
+#[derive(Deserialize, Clone, PartialEq, Eq, Debug)]
+#[serde(crate = "rocket::serde", try_from = "String")]
+pub enum BindAddress {
+    /// Listen on `http_port` and `https_port` of this address (`::` only accepts IPv6).
+    Ip(IpAddr),
+    /// Listen on `http.sock` and `https.sock` in this directory, e.g. behind a reverse proxy.
+    Unix(PathBuf),
+}
+
+/// How Let's Encrypt validates control of the domains.
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(crate = "rocket::serde")]
+pub enum AcmeChallenge {
+    /// Answered during the TLS handshake on the HTTPS port.
+    #[default]
+    #[serde(rename = "tls-alpn-01")]
+    TlsAlpn01,
+    /// Answered under `/.well-known/acme-challenge/` on the HTTP port, for deployments behind
+    /// load balancers that terminate or don't pass through ALPN.
+    #[serde(rename = "http-01")]
+    Http01,
+}
+
+/// A PEM certificate chain and private key, reloaded when they change.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(crate = "rocket::serde")]
+pub struct TlsFiles {
+    pub cert: PathBuf,
+    pub key: PathBuf,
+}
+
+/// Optional parts of the site that can be switched off.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(crate = "rocket::serde", default)]
+pub struct Features {
+    /// Replace `:shortcode:`s with their emoji, and emoji characters with the SVGs in
+    /// [`Render::emoji_directory`] except on pages whose head has
+    /// `<meta name="emojis" content="native">`.
+    pub emojis: bool,
+    /// Mount the `/search` endpoint used by the search overlay.
+    pub search: bool,
+    /// Link to static files by content-hashed URLs, served with a year long `Cache-Control`.
+    pub fingerprints: bool,
+}
+
+/// How markdown pages are rendered into the page cache.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(crate = "rocket::serde", default)]
+pub struct Render {
+    /// HTML template each page is inserted into, relative to the pages directory.
+    pub template: PathBuf,
+    /// Directory of `<code points>.svg` emoji files, relative to the pages directory.
+    pub emoji_directory: PathBuf,
+    pub emoji_mode: EmojiMode,
+    pub dates: PageDates,
+    pub tables: bool,
+    pub strikethrough: bool,
+    pub heading_attributes: bool,
+    pub footnotes: bool,
+    pub task_lists: bool,
+    pub smart_punctuation: bool,
+}
+
+/// `<head>` entries added to pages that don't set their own.
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(crate = "rocket::serde", default)]
+pub struct Metadata {
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub description: Option<String>,
+}
+
+/// `Cache-Control` header values, no header is sent when unset.
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(crate = "rocket::serde", default)]
+pub struct CacheControl {
+    pub pages: Option<String>,
+    /// Font files (`font/*`), falling back to `files`.
+    pub fonts: Option<String>,
+    /// Image files (`image/*`), falling back to `files`.
+    pub images: Option<String>,
+    /// Every other static file.
+    pub files: Option<String>,
+}
+
+impl Default for SiteConfig {
+    fn default() -> Self {
+        Self {
+            domains: Vec::new(),
+            email: None,
+            bind: vec![BindAddress::Ip(Ipv4Addr::UNSPECIFIED.into())],
+            unix_socket_mode: 0o660,
+            unix_socket_group: None,
+            http_port: 80,
+            https_port: 443,
+            http_only: false,
+            user: None,
+            group: None,
+            lets_encrypt_cache: PathBuf::from("lets_encrypt_cache"),
+            lets_encrypt_staging: false,
+            acme_directory: None,
+            acme_root_certificate: None,
+            acme_challenge: AcmeChallenge::default(),
+            status_token: None,
+            tls: None,
+            pages_dir: None,
+            features: Features::default(),
+            render: Render::default(),
+            metadata: Metadata::default(),
+            cache_control: CacheControl::default(),
+        }
+    }
+}
+
+impl Default for Features {
+    fn default() -> Self {
+        Self {
+            emojis: true,
+            search: true,
+            fingerprints: true,
+        }
+    }
+}
+
+impl Default for Render {
+    fn default() -> Self {
+        Self {
+            template: PathBuf::from("templates/template.html"),
+            emoji_directory: PathBuf::from("emojis"),
+            emoji_mode: EmojiMode::default(),
+            dates: PageDates::Filesystem,
+            tables: true,
+            strikethrough: true,
+            heading_attributes: true,
+            footnotes: false,
+            task_lists: false,
+            smart_punctuation: false,
+        }
+    }
+}
+
+impl FromStr for BindAddress {
+    type Err = String;
+
+    fn from_str(address: &str) -> Result<Self, Self::Err> {
+        match address.strip_prefix("unix:") {
+            Some(directory) => Ok(Self::Unix(PathBuf::from(directory))),
+            None => address
+                .trim_start_matches('[')
+                .trim_end_matches(']')
+                .parse()
+                .map(Self::Ip)
+                .map_err(|_| format!("{address:?} is neither an IP address nor unix:<directory>")),
+        }
+    }
+}
+
+impl TryFrom<String> for BindAddress {
+    type Error = String;
+
+    fn try_from(address: String) -> Result<Self, Self::Error> {
+        address.parse()
+    }
+}
+
+impl CacheControl {
+    /// The header value for a static file, picked by the media type of its extension.
+    pub fn for_file(&self, path: &Path) -> Option<&str> {
+        let media_type = path
+            .extension()
+            .and_then(|extension| ContentType::from_extension(&extension.to_string_lossy()));
+        let class = match media_type
+            .as_ref()
+            .map(|media_type| media_type.top().as_str())
+        {
+            Some("font") => &self.fonts,
+            Some("image") => &self.images,
+            _ => &self.files,
+        };
+        class.as_deref().or(self.files.as_deref())
+    }
+}
+
+impl SiteConfig {
+    /// Loads the configuration from a TOML file (which may not exist) and the environment.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Box<figment::Error>> {
+        Figment::new()
+            .merge(Toml::file(path))
+            .merge(Env::prefixed("AUXV_").split("__"))
+            .extract()
+            .map_err(Box::new)
+    }
+
+    /// The canonical domain of the site.
+    pub fn primary_domain(&self) -> Option<&str> {
+        self.domains.first().map(String::as_str)
+    }
+
+    /// The bearer token of the `/_status` endpoints, `None` when it's unset or empty (which leaves
+    /// them unmounted).
+    pub fn status_token(&self) -> Option<&str> {
+        self.status_token
+            .as_deref()
+            .filter(|status_token| !status_token.is_empty())
+    }
+
+    /// Where the pages are read from: `pages_dir` if set, otherwise the [`Content::default`].
+    pub fn content(&self) -> Content {
+        self.pages_dir
+            .clone()
+            .map(Content::Directory)
+            .unwrap_or_default()
+    }
+}
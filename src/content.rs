@@ -0,0 +1,196 @@
+//! 📂 Access to the `pages/` tree the site is rendered from.
+//!
+//! The tree is either read from a directory on disk or, with the `embed` feature, from a copy
+//! compiled into the binary, so a release build can be a single self-contained artifact.
+//!
+//! All paths taken and returned here are relative to the root of the `pages/` tree.
+
+#[cfg(feature = "embed")]
+use std::time::{Duration, UNIX_EPOCH};
+use std::{
+    collections::HashMap,
+    io::{Error, ErrorKind, Result},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use rocket::fs::NamedFile;
+#[cfg(feature = "embed")]
+use rocket::http::ContentType;
+
+use crate::git_dates;
+
+#[cfg(feature = "embed")]
+static EMBEDDED_PAGES: include_dir::Dir<'static> =
+    include_dir::include_dir!("$CARGO_MANIFEST_DIR/pages");
+
+// `BUILD_TIME` and `EMBEDDED_GIT_DATES`, written by `build.rs`:
+#[cfg(feature = "embed")]
+include!(concat!(env!("OUT_DIR"), "/embedded_dates.rs"));
+
+/// Where the `pages/` tree is read from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Content {
+    Directory(PathBuf),
+    #[cfg(feature = "embed")]
+    Embedded,
+}
+
+impl Default for Content {
+    /// The embedded pages when built with `embed`, otherwise `./pages`.
+    fn default() -> Self {
+        #[cfg(feature = "embed")]
+        {
+            Self::Embedded
+        }
+
+        #[cfg(not(feature = "embed"))]
+        {
+            Self::Directory(PathBuf::from("./pages"))
+        }
+    }
+}
+
+/// A static file from the `pages/` tree, ready to be sent as a response.
+#[derive(Responder)]
+pub enum File {
+    Disk(NamedFile),
+    #[cfg(feature = "embed")]
+    Embedded(&'static [u8], ContentType),
+}
+
+impl Content {
+    /// The on-disk directory the pages are read from, or `None` when they are embedded.
+    pub fn directory(&self) -> Option<&Path> {
+        match self {
+            Self::Directory(directory) => Some(directory),
+            #[cfg(feature = "embed")]
+            Self::Embedded => None,
+        }
+    }
+
+    /// Recursively lists every file below `directory`.
+    pub fn read_dir_all(&self, directory: impl AsRef<Path>) -> Result<Vec<PathBuf>> {
+        let mut files = Vec::new();
+
+        match self {
+            Self::Directory(root) => {
+                let mut queue = vec![root.join(directory)];
+                while let Some(dir) = queue.pop() {
+                    for entry in std::fs::read_dir(dir)? {
+                        let path = entry?.path();
+                        if path.is_dir() {
+                            queue.push(path);
+                        } else {
+                            files.push(path.strip_prefix(root).unwrap().to_path_buf());
+                        }
+                    }
+                }
+            }
+            #[cfg(feature = "embed")]
+            Self::Embedded => {
+                let directory = directory.as_ref();
+                let root = match directory.as_os_str().is_empty() {
+                    true => &EMBEDDED_PAGES,
+                    false => EMBEDDED_PAGES
+                        .get_dir(directory)
+                        .ok_or_else(|| not_embedded(directory))?,
+                };
+
+                let mut queue = vec![root];
+                while let Some(dir) = queue.pop() {
+                    files.extend(dir.files().map(|file| file.path().to_path_buf()));
+                    queue.extend(dir.dirs());
+                }
+            }
+        }
+
+        Ok(files)
+    }
+
+    pub fn read(&self, path: impl AsRef<Path>) -> Result<Vec<u8>> {
+        match self {
+            Self::Directory(root) => std::fs::read(root.join(path)),
+            #[cfg(feature = "embed")]
+            Self::Embedded => embedded_file(path.as_ref()).map(|file| file.contents().to_vec()),
+        }
+    }
+
+    pub fn read_to_string(&self, path: impl AsRef<Path>) -> Result<String> {
+        String::from_utf8(self.read(path)?)
+            .map_err(|error| Error::new(ErrorKind::InvalidData, error))
+    }
+
+    /// Returns the `(created, last_modified)` times of a file.
+    ///
+    /// Embedded files without recorded times (e.g. built on a file system without creation times)
+    /// get the time of the build.
+    pub fn dates(&self, path: impl AsRef<Path>) -> Result<(SystemTime, SystemTime)> {
+        match self {
+            Self::Directory(root) => {
+                let metadata = std::fs::metadata(root.join(path))?;
+                let modified = metadata.modified()?;
+                Ok((metadata.created().unwrap_or(modified), modified))
+            }
+            #[cfg(feature = "embed")]
+            Self::Embedded => {
+                let build_time = UNIX_EPOCH + Duration::from_secs(BUILD_TIME);
+                Ok(embedded_file(path.as_ref())?
+                    .metadata()
+                    .map_or((build_time, build_time), |metadata| {
+                        (metadata.created(), metadata.modified())
+                    }))
+            }
+        }
+    }
+
+    /// Returns the `(created, last_modified)` commit times of every file git knows about, see
+    /// [`git_dates::read_git_dates`]. The history of embedded pages is read when they are
+    /// embedded.
+    pub fn git_dates(&self) -> Result<HashMap<PathBuf, (SystemTime, SystemTime)>> {
+        match self {
+            Self::Directory(root) => git_dates::read_git_dates(root),
+            #[cfg(feature = "embed")]
+            Self::Embedded => Ok(EMBEDDED_GIT_DATES
+                .iter()
+                .map(|&(path, created, modified)| {
+                    let time = |seconds| UNIX_EPOCH + Duration::from_secs(seconds);
+                    (PathBuf::from(path), (time(created), time(modified)))
+                })
+                .collect()),
+        }
+    }
+
+    pub async fn open(&self, path: impl AsRef<Path>) -> Result<File> {
+        match self {
+            Self::Directory(root) => NamedFile::open(root.join(path)).await.map(File::Disk),
+            #[cfg(feature = "embed")]
+            Self::Embedded => {
+                let path = path.as_ref();
+                let content_type = path
+                    .extension()
+                    .and_then(|extension| ContentType::from_extension(&extension.to_string_lossy()))
+                    .unwrap_or(ContentType::Binary);
+                Ok(File::Embedded(
+                    embedded_file(path)?.contents(),
+                    content_type,
+                ))
+            }
+        }
+    }
+}
+
+#[cfg(feature = "embed")]
+fn embedded_file(path: &Path) -> Result<&'static include_dir::File<'static>> {
+    EMBEDDED_PAGES
+        .get_file(path)
+        .ok_or_else(|| not_embedded(path))
+}
+
+#[cfg(feature = "embed")]
+fn not_embedded(path: &Path) -> Error {
+    Error::new(
+        ErrorKind::NotFound,
+        format!("{} is not embedded", path.display()),
+    )
+}
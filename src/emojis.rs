@@ -1,22 +1,92 @@
 use std::{
-    fs::{read_dir, read_to_string},
-    io::Result,
+    borrow::Cow,
+    cell::RefCell,
+    collections::BTreeMap,
+    fmt::Write,
+    io::{Error, ErrorKind, Result},
     path::PathBuf,
+    rc::Rc,
 };
 
 use aho_corasick::{AhoCorasick, MatchKind};
+use pulldown_cmark::{CowStr, Event, Tag, TagEnd};
+use rocket::serde::Deserialize;
+
+use crate::{
+    assets::fingerprinted_path,
+    compression::Representation,
+    content::Content,
+    html::{HtmlContext, ends_block, escape_html},
+};
+
+/// Sizes emoji like the surrounding text.
+const EMOJI_STYLE: &str =
+    "height: 1em; width: 1em; margin: 0 .05em 0 .1em; vertical-align: -0.1em;";
+
+/// How emoji are put into the rendered pages.
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(crate = "rocket::serde", rename_all = "lowercase")]
+pub enum EmojiMode {
+    /// Every emoji's SVG markup is copied into the page.
+    #[default]
+    Inline,
+    /// `<use>` references into one sprite sheet of every emoji on the site, served from a
+    /// fingerprinted URL like `sprite.0123456789abcdef.svg` in the emoji directory.
+    Sprite,
+    /// `<img>` tags referencing each emoji's SVG file.
+    Image,
+}
+
+/// The sprite sheet of every emoji used on the site, see [`EmojiMode::Sprite`].
+pub struct EmojiSprite {
+    /// Where the sprite sheet is served from, relative to the pages directory, fingerprinted so
+    /// it can be cached forever.
+    pub path: PathBuf,
+    /// `sprite.svg` in the emoji directory, which the pages reference until
+    /// [`EmojiSprite::link`] points them at `path` (and which keeps serving the latest sprite
+    /// sheet for pages rendered before it was fingerprinted).
+    pub original: PathBuf,
+    pub svg: Representation,
+}
+
+impl EmojiSprite {
+    /// Points the sprite references in a page's `html` at the fingerprinted path.
+    pub fn link(&self, html: &str) -> String {
+        html.replace(
+            &format!("href=\"/{}#", self.original.display()),
+            &format!("href=\"/{}#", self.path.display()),
+        )
+    }
+}
 
 pub struct EmojiParser {
+    content: Content,
     svg_directory: PathBuf,
+    mode: EmojiMode,
     aho_corasick: AhoCorasick,
+    /// Every SVG loaded so far by file name (without `.svg`), which is also every emoji
+    /// substituted so far.
+    svgs: RefCell<BTreeMap<String, Rc<EmojiSvg>>>,
+    /// `:shortcode:`s found that aren't an emoji's, or whose emoji has no SVG, since the last
+    /// [`EmojiParser::take_unknown_shortcodes`].
+    unknown_shortcodes: RefCell<Vec<String>>,
+}
+
+/// An emoji's SVG file, split around its root `<svg>` tag.
+struct EmojiSvg {
+    /// The root tag's attributes, e.g. ` xmlns="http://www.w3.org/2000/svg" viewBox="0 0 36 36"`.
+    attributes: String,
+    contents: String,
 }
 
 impl EmojiParser {
-    pub fn new(svg_directory: PathBuf) -> Result<Self> {
-        let pattern = read_dir(&svg_directory)?
-            .filter_map(|entry| {
-                let file_name = entry.ok()?.file_name();
-                let character_pattern = file_name
+    pub fn new(content: Content, svg_directory: PathBuf, mode: EmojiMode) -> Result<Self> {
+        let pattern = content
+            .read_dir_all(&svg_directory)?
+            .into_iter()
+            .filter_map(|path| {
+                let character_pattern = path
+                    .file_name()?
                     .to_string_lossy()
                     .strip_suffix(".svg")?
                     .split('-')
@@ -32,54 +102,290 @@ impl EmojiParser {
             .collect::<Vec<_>>();
 
         Ok(Self {
+            content,
             svg_directory,
+            mode,
             aho_corasick: AhoCorasick::builder()
                 .match_kind(MatchKind::LeftmostLongest)
                 .build(&pattern)
-                .unwrap(),
+                .map_err(Error::other)?,
+            svgs: RefCell::default(),
+            unknown_shortcodes: RefCell::default(),
         })
     }
 
-    pub fn inline_from_directory(&self, haystack: &str) -> String {
+    /// Substitutes the emoji in a page's markdown `events`: those in text, and those in the text of
+    /// raw HTML outside tags, comments and [`VERBATIM_ELEMENTS`](crate::html::VERBATIM_ELEMENTS). Code, and image alt text (which
+    /// can't hold markup), is left alone.
+    ///
+    /// GitHub's `:shortcode:`s (e.g. `:wave:`) in text are resolved to their emoji first. When
+    /// `native`, that's all that's done and the emoji are left as characters.
+    pub fn substitute<'a>(&self, events: Vec<Event<'a>>, native: bool) -> Result<Vec<Event<'a>>> {
+        let mut substituted = Vec::with_capacity(events.len());
+        let mut in_code_block = false;
+        let mut image_depth = 0usize;
+        // Raw HTML is split into many events (e.g. one per line of a block), so its state carries
+        // over between them:
+        let mut html = HtmlContext::default();
+
+        for event in events {
+            match event {
+                Event::Start(Tag::CodeBlock(_)) => in_code_block = true,
+                Event::End(TagEnd::CodeBlock) => in_code_block = false,
+                // An element left open can't leak past the end of its block:
+                Event::End(tag) if ends_block(&tag) => html = HtmlContext::default(),
+                Event::Start(Tag::Image { .. }) => image_depth += 1,
+                Event::End(TagEnd::Image) => image_depth -= 1,
+                Event::Text(text) if !in_code_block && !html.in_verbatim_element() => {
+                    let as_characters = native || image_depth > 0;
+                    let text = self.resolve_shortcodes(&text, as_characters);
+                    if as_characters {
+                        substituted.push(Event::Text(CowStr::from(text.into_owned())));
+                        continue;
+                    }
+
+                    let mut last_end = 0;
+                    for emoji in self.aho_corasick.find_iter(text.as_ref()) {
+                        if last_end < emoji.start() {
+                            substituted.push(Event::Text(CowStr::from(
+                                text[last_end..emoji.start()].to_owned(),
+                            )));
+                        }
+                        let tag = self.code_point_to_svg_tag(text[emoji.range()].chars())?;
+                        substituted.push(Event::InlineHtml(CowStr::from(tag)));
+                        last_end = emoji.end();
+                    }
+                    if last_end < text.len() {
+                        substituted.push(Event::Text(CowStr::from(text[last_end..].to_owned())));
+                    }
+                    continue;
+                }
+                Event::Html(raw) if !native => {
+                    substituted.push(Event::Html(CowStr::from(
+                        html.substitute(&raw, |text| self.inline_from_directory(text))?,
+                    )));
+                    continue;
+                }
+                Event::InlineHtml(raw) if !native => {
+                    substituted.push(Event::InlineHtml(CowStr::from(
+                        html.substitute(&raw, |text| self.inline_from_directory(text))?,
+                    )));
+                    continue;
+                }
+                _ => {}
+            }
+            substituted.push(event);
+        }
+
+        Ok(substituted)
+    }
+
+    /// Replaces the `:shortcode:`s in `text` with their emoji, recording any that aren't an
+    /// emoji's, or whose emoji has no SVG unless it is left `as_characters` anyway.
+    ///
+    /// Shortcodes have to stand on their own, so those without letters (e.g. in `12:30:00`) or
+    /// with a letter, digit or colon right before or after them (e.g. in `std::fs::read` or
+    /// `host:port:`) aren't taken as shortcodes.
+    fn resolve_shortcodes<'a>(&self, text: &'a str, as_characters: bool) -> Cow<'a, str> {
+        let is_shortcode_character =
+            |c: char| c.is_ascii_alphanumeric() || matches!(c, '_' | '+' | '-');
+        let is_boundary = |c: Option<char>| !c.is_some_and(|c| c.is_alphanumeric() || c == ':');
+
+        let mut resolved = String::new();
+        let mut remaining = text;
+        while let Some(start) = remaining.find(':') {
+            let after_colon = &remaining[start + 1..];
+            let length = after_colon
+                .find(|c| !is_shortcode_character(c))
+                .unwrap_or(after_colon.len());
+            let shortcode = &after_colon[..length];
+            let before = text[..text.len() - remaining.len() + start]
+                .chars()
+                .next_back();
+
+            let is_shortcode = !shortcode.is_empty()
+                && after_colon[length..].starts_with(':')
+                && is_boundary(before)
+                && is_boundary(after_colon[length + 1..].chars().next());
+            if is_shortcode {
+                if let Some(emoji) = ::emojis::get_by_shortcode(shortcode) {
+                    if !as_characters && !self.has_svg(emoji.as_str()) {
+                        self.unknown_shortcodes
+                            .borrow_mut()
+                            .push(shortcode.to_owned());
+                    }
+                    resolved.push_str(&remaining[..start]);
+                    resolved.push_str(emoji.as_str());
+                    remaining = &after_colon[length + 1..];
+                    continue;
+                }
+                if shortcode.contains(|c: char| c.is_ascii_alphabetic()) {
+                    self.unknown_shortcodes
+                        .borrow_mut()
+                        .push(shortcode.to_owned());
+                }
+            }
+            // The closing colon could open the next shortcode:
+            resolved.push_str(&remaining[..=start]);
+            remaining = after_colon;
+        }
+
+        match resolved.is_empty() {
+            true => Cow::Borrowed(text),
+            false => {
+                resolved.push_str(remaining);
+                Cow::Owned(resolved)
+            }
+        }
+    }
+
+    /// Whether there is an SVG for the whole of `emoji` (Twemoji's file names leave out the
+    /// trailing variation selector).
+    fn has_svg(&self, emoji: &str) -> bool {
+        self.aho_corasick.find(emoji).is_some_and(|found| {
+            found.start() == 0 && matches!(&emoji[found.end()..], "" | "\u{fe0f}")
+        })
+    }
+
+    /// The `:shortcode:`s found that aren't an emoji's, or whose emoji has no SVG, since the last
+    /// call.
+    pub fn take_unknown_shortcodes(&self) -> Vec<String> {
+        self.unknown_shortcodes.take()
+    }
+
+    pub fn inline_from_directory(&self, haystack: &str) -> Result<String> {
         let mut inlined_content = String::with_capacity(haystack.len());
+        let mut error = None;
         self.aho_corasick.replace_all_with(
             haystack,
             &mut inlined_content,
-            |_, code_point_str, destination| {
-                destination.push_str(&self.code_point_to_svg_tag(code_point_str.chars()));
-                true
+            |_, code_point_str, destination| match self
+                .code_point_to_svg_tag(code_point_str.chars())
+            {
+                Ok(tag) => {
+                    destination.push_str(&tag);
+                    true
+                }
+                Err(svg_error) => {
+                    error = Some(svg_error);
+                    false
+                }
             },
         );
-        inlined_content
+        error.map_or(Ok(inlined_content), Err)
+    }
+
+    /// The sprite sheet of every emoji substituted so far, `None` unless in
+    /// [`EmojiMode::Sprite`].
+    pub fn sprite(&self) -> Result<Option<EmojiSprite>> {
+        if self.mode != EmojiMode::Sprite {
+            return Ok(None);
+        }
+
+        let mut sprite = String::from("<svg xmlns=\"http://www.w3.org/2000/svg\">");
+        for (name, svg) in self.svgs.borrow().iter() {
+            let view_box = svg
+                .attributes
+                .split_once("viewBox=\"")
+                .and_then(|(_, view_box)| view_box.split_once('"'))
+                .map_or("0 0 36 36", |(view_box, _)| view_box);
+
+            let _ = write!(
+                sprite,
+                "<symbol id=\"{name}\" viewBox=\"{view_box}\">{}</symbol>",
+                svg.contents
+            );
+        }
+        sprite.push_str("</svg>");
+
+        let original = self.sprite_path();
+        Ok(Some(EmojiSprite {
+            path: fingerprinted_path(&original, sprite.as_bytes()),
+            original,
+            svg: Representation::new(sprite)?,
+        }))
+    }
+
+    fn sprite_path(&self) -> PathBuf {
+        self.svg_directory.join("sprite.svg")
     }
 
-    fn code_point_to_svg_tag(&self, code_point: impl Iterator<Item = char> + Clone) -> String {
-        let file_path = self
-            .svg_directory
-            .to_path_buf()
-            .join(
-                code_point
-                    .clone()
-                    .map(|c| format!("{:x}", c as u32))
-                    .collect::<Vec<_>>()
-                    .join("-"),
+    /// Loads the SVG file `<name>.svg`, or returns it from the previous load.
+    fn load_svg(&self, name: &str) -> Result<Rc<EmojiSvg>> {
+        if let Some(svg) = self.svgs.borrow().get(name) {
+            return Ok(svg.clone());
+        }
+
+        let path = self.svg_directory.join(name).with_extension("svg");
+        let svg = self.content.read_to_string(&path)?;
+        let not_svg = || {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("{} isn't an SVG", path.display()),
             )
-            .with_extension("svg");
-
-        let svg = read_to_string(file_path)
-            .unwrap()
-            .lines()
-            .filter(|line| {
-                let line_trim = line.trim_start();
-                !line_trim.starts_with("<?xml") && !line_trim.starts_with("<!--")
-            })
+        };
+
+        // Anything before the root tag (an XML declaration or comments) is dropped:
+        let (attributes, contents) = svg
+            .split_once("<svg")
+            .and_then(|(_, svg)| svg.split_once('>'))
+            .ok_or_else(not_svg)?;
+        let contents = contents
+            .trim_end()
+            .strip_suffix("</svg>")
+            .ok_or_else(not_svg)?;
+
+        let svg = Rc::new(EmojiSvg {
+            attributes: attributes.to_owned(),
+            contents: contents.to_owned(),
+        });
+        self.svgs.borrow_mut().insert(name.to_owned(), svg.clone());
+        Ok(svg)
+    }
+
+    fn code_point_to_svg_tag(
+        &self,
+        code_point: impl Iterator<Item = char> + Clone,
+    ) -> Result<String> {
+        let name = code_point
+            .clone()
+            .map(|c| format!("{:x}", c as u32))
             .collect::<Vec<_>>()
-            .join("\n");
+            .join("-");
+        let file_path = self.svg_directory.join(&name).with_extension("svg");
+        let emoji = code_point.collect::<String>();
+        // Screen readers skip `alt` on an `<svg>`, so it's named by `aria-label` and `<title>`
+        // while `data-emoji` keeps the character for `emoji_copy_paste.js`:
+        let label = escape_html(
+            ::emojis::get(&emoji)
+                .or_else(|| ::emojis::get(&emoji.replace('\u{fe0f}', "")))
+                .map_or(emoji.as_str(), |emoji| emoji.name()),
+        );
+        let svg_attributes = format!(
+            "class=\"emoji\" draggable=\"false\" style=\"{EMOJI_STYLE}\" role=\"img\" aria-label=\"{label}\" data-emoji=\"{emoji}\""
+        );
 
-        format!(
-            "<svg class=\"emoji\" draggable=\"false\" style=\"height: 1em; width: 1em; margin: 0 .05em 0 .1em; vertical-align: -0.1em;\" alt=\"{}\"{}",
-            code_point.collect::<String>(),
-            &svg[4..]
-        )
+        Ok(match self.mode {
+            EmojiMode::Inline => {
+                let svg = self.load_svg(&name)?;
+                format!(
+                    "<svg {svg_attributes}{}><title>{label}</title>{}</svg>",
+                    svg.attributes, svg.contents
+                )
+            }
+            EmojiMode::Sprite => {
+                // Loaded now so the sprite sheet includes it:
+                self.load_svg(&name)?;
+                format!(
+                    "<svg {svg_attributes}><title>{label}</title><use href=\"/{}#{name}\"/></svg>",
+                    self.sprite_path().display()
+                )
+            }
+            // `alt` already names an `<img>`, and is shown if the image can't be loaded:
+            EmojiMode::Image => format!(
+                "<img class=\"emoji\" draggable=\"false\" style=\"{EMOJI_STYLE}\" alt=\"{emoji}\" title=\"{label}\" data-emoji=\"{emoji}\" src=\"/{}\">",
+                file_path.display()
+            ),
+        })
     }
 }
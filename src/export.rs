@@ -0,0 +1,51 @@
+use std::{
+    fs::{create_dir_all, write},
+    io::Result,
+    path::Path,
+};
+
+use crate::{content::Content, pages::Site};
+
+/// Writes the pages of `site` and every non-markdown asset of `content` into `output` as a static
+/// site.
+///
+/// Each page is written as `<path>/index.html` so the URLs match the ones served by Rocket, and the
+/// 404 page is additionally written to `404.html` where most static hosts look for it.
+pub fn export_site(site: &Site, content: &Content, output: &Path) -> Result<()> {
+    for (url, page) in &site.pages {
+        let directory = output.join(url);
+        create_dir_all(&directory)?;
+        write(directory.join("index.html"), page.html.body)?;
+    }
+
+    if let Some(page) = site.pages.get(Path::new("404")) {
+        write(output.join("404.html"), page.html.body)?;
+    }
+
+    for path in content.read_dir_all("")? {
+        if path.extension().is_some_and(|extension| extension == "md") {
+            continue;
+        }
+
+        let destination = output.join(&path);
+        if let Some(parent) = destination.parent() {
+            create_dir_all(parent)?;
+        }
+        write(destination, content.read(&path)?)?;
+    }
+
+    if let Some(sprite) = &site.emoji_sprite {
+        write(output.join(&sprite.path), sprite.svg.body)?;
+        write(output.join(&sprite.original), sprite.svg.body)?;
+    }
+
+    // The pages link to the fingerprinted copies, stylesheets with their own links rewritten:
+    for (fingerprinted, original) in site.fingerprints.iter() {
+        match site.fingerprints.stylesheet(fingerprinted) {
+            Some(stylesheet) => write(output.join(fingerprinted), stylesheet.body)?,
+            None => write(output.join(fingerprinted), content.read(original)?)?,
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,75 @@
+//! 🕰️ Page dates read from the commit history of the git repository containing the pages.
+//!
+//! Only uses `std`, as `build.rs` also includes it to embed the dates along with the pages.
+
+use std::{
+    collections::HashMap,
+    io::{Error, Result},
+    path::{Path, PathBuf},
+    process::Command,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Reads the `(created, last_modified)` commit times of every file under `directory`, keyed by their
+/// path relative to it.
+///
+/// Renames are followed, so a moved page keeps the creation date of its original path.
+pub fn read_git_dates(directory: &Path) -> Result<HashMap<PathBuf, (SystemTime, SystemTime)>> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(directory)
+        .args([
+            "-c",
+            "core.quotePath=false",
+            "log",
+            "--format=%x00%ct",
+            "--name-status",
+            "-M",
+            "--relative",
+            "--",
+            ".",
+        ])
+        .output()?;
+
+    if !output.status.success() {
+        return Err(Error::other(
+            String::from_utf8_lossy(&output.stderr).trim().to_owned(),
+        ));
+    }
+
+    let mut dates = HashMap::new();
+    // Earlier paths of renamed files to their current path:
+    let mut renamed: HashMap<PathBuf, PathBuf> = HashMap::new();
+    let mut commit_time = UNIX_EPOCH;
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        if let Some(timestamp) = line.strip_prefix('\0') {
+            commit_time =
+                UNIX_EPOCH + Duration::from_secs(timestamp.parse().map_err(Error::other)?);
+            continue;
+        }
+
+        // `<status>\t<path>`, or `R<similarity>\t<old path>\t<new path>` for renames:
+        let mut fields = line.split('\t');
+        let (Some(status), Some(path)) = (fields.next(), fields.next()) else {
+            continue;
+        };
+        let (old_path, path) = match fields.next() {
+            Some(new_path) if status.starts_with('R') => (Some(path), new_path),
+            _ => (None, path),
+        };
+
+        let path = PathBuf::from(path);
+        let path = renamed.get(&path).cloned().unwrap_or(path);
+        // Commits are listed newest first, so the first one seen is the last modification and
+        // the final one is the creation:
+        dates
+            .entry(path.clone())
+            .and_modify(|(created, _)| *created = commit_time)
+            .or_insert((commit_time, commit_time));
+        if let Some(old_path) = old_path {
+            renamed.insert(PathBuf::from(old_path), path);
+        }
+    }
+
+    Ok(dates)
+}
@@ -0,0 +1,154 @@
+//! 🏷️ Helpers for the HTML put together outside of pulldown-cmark, and for reading the raw HTML
+//! in markdown.
+
+use std::io::Result;
+
+use pulldown_cmark::TagEnd;
+
+/// Elements of raw HTML whose contents are left as they are, as markup there would either show up
+/// literally or break the element.
+pub(crate) const VERBATIM_ELEMENTS: [&str; 7] =
+    ["code", "pre", "script", "style", "svg", "textarea", "title"];
+
+/// Escapes `s` for use in HTML text and quoted attribute values.
+pub(crate) fn escape_html(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Whether `tag` ends a block, which an element left open in its raw HTML can't leak past.
+pub(crate) fn ends_block(tag: &TagEnd) -> bool {
+    matches!(
+        tag,
+        TagEnd::Paragraph
+            | TagEnd::Heading(_)
+            | TagEnd::HtmlBlock
+            | TagEnd::Item
+            | TagEnd::TableCell
+            | TagEnd::FootnoteDefinition
+    )
+}
+
+/// Where a scan through raw HTML is, carried over between the events it is split into (e.g. one
+/// per line of a block).
+#[derive(Default)]
+pub(crate) struct HtmlContext {
+    in_comment: bool,
+    in_tag: bool,
+    /// The quote an attribute value inside the tag was opened with.
+    quote: Option<char>,
+    /// The open tag starts one of the [`VERBATIM_ELEMENTS`], counted once it turns out not to be
+    /// self-closing (e.g. `<svg .../>`).
+    in_verbatim_tag: bool,
+    /// The last character of the open tag was a `/` outside any quotes.
+    self_closing: bool,
+    /// How many [`VERBATIM_ELEMENTS`] are open.
+    verbatim_depth: usize,
+}
+
+impl HtmlContext {
+    /// Whether the scan is inside one of the [`VERBATIM_ELEMENTS`].
+    pub(crate) fn in_verbatim_element(&self) -> bool {
+        self.verbatim_depth > 0
+    }
+
+    /// Follows the scan through `html` without changing it.
+    pub(crate) fn scan(&mut self, html: &str) {
+        let _ = self.substitute(html, |text| Ok(text.to_owned()));
+    }
+
+    /// Replaces the text of `html` outside tags, comments and [`VERBATIM_ELEMENTS`] with what
+    /// `substitute_text` makes of it.
+    pub(crate) fn substitute(
+        &mut self,
+        mut html: &str,
+        mut substitute_text: impl FnMut(&str) -> Result<String>,
+    ) -> Result<String> {
+        let mut substituted = String::with_capacity(html.len());
+
+        while !html.is_empty() {
+            let end = if self.in_comment {
+                match html.find("-->") {
+                    Some(index) => {
+                        self.in_comment = false;
+                        index + "-->".len()
+                    }
+                    None => html.len(),
+                }
+            } else if self.in_tag {
+                let mut end = html.len();
+                for (index, c) in html.char_indices() {
+                    match (self.quote, c) {
+                        (Some(quote), c) if c == quote => self.quote = None,
+                        (Some(_), _) => {}
+                        (None, '"' | '\'') => self.quote = Some(c),
+                        (None, '>') => {
+                            if self.in_verbatim_tag && !self.self_closing {
+                                self.verbatim_depth += 1;
+                            }
+                            self.in_tag = false;
+                            self.in_verbatim_tag = false;
+                            end = index + 1;
+                            break;
+                        }
+                        (None, c) => self.self_closing = c == '/',
+                    }
+                }
+                end
+            } else if html.starts_with("<!--") {
+                self.in_comment = true;
+                "<!--".len()
+            } else if html.starts_with('<')
+                && html[1..].starts_with(|c: char| c.is_ascii_alphabetic() || c == '/' || c == '!')
+            {
+                self.in_tag = true;
+                self.self_closing = false;
+                let (closing, name) = match html[1..].strip_prefix('/') {
+                    Some(name) => (true, name),
+                    None => (false, &html[1..]),
+                };
+                let name_length = name
+                    .find(|c: char| !c.is_ascii_alphanumeric())
+                    .unwrap_or(name.len());
+                if VERBATIM_ELEMENTS
+                    .iter()
+                    .any(|element| element.eq_ignore_ascii_case(&name[..name_length]))
+                {
+                    match closing {
+                        true => self.verbatim_depth = self.verbatim_depth.saturating_sub(1),
+                        false => self.in_verbatim_tag = true,
+                    }
+                }
+                1
+            } else {
+                // A `<` that doesn't start a tag is text:
+                let end = html
+                    .char_indices()
+                    .skip(1)
+                    .find_map(|(index, c)| (c == '<').then_some(index))
+                    .unwrap_or(html.len());
+                match self.verbatim_depth {
+                    0 => substituted.push_str(&substitute_text(&html[..end])?),
+                    _ => substituted.push_str(&html[..end]),
+                }
+                html = &html[end..];
+                continue;
+            };
+
+            substituted.push_str(&html[..end]);
+            html = &html[end..];
+        }
+
+        Ok(substituted)
+    }
+}
@@ -0,0 +1,376 @@
+use std::{
+    io::{Error, ErrorKind, Result},
+    sync::Arc,
+    time::Duration,
+};
+
+use auxv_dot_org::canonical_host::CanonicalDomain;
+use rustls_acme::ResolvesServerCertAcme;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    task::JoinSet,
+    time::timeout,
+};
+
+use crate::sockets::{Socket, Sockets};
+
+/// Largest request line + headers accepted before giving up on a client.
+const MAX_REQUEST_HEAD_LENGTH: usize = 8 * 1024;
+/// Where ACME HTTP-01 challenge tokens are requested from.
+const ACME_CHALLENGE_PATH: &str = "/.well-known/acme-challenge/";
+/// How long a client gets to send its request head, and to receive the response.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// ↪️ A plain HTTP server that redirects every request to the same path and query over HTTPS.
+///
+/// Only requests for one of the site's domains are redirected (always to the canonical domain),
+/// anything else is answered with `421 Misdirected Request`. When ACME HTTP-01 challenges are
+/// enabled, `/.well-known/acme-challenge/<token>` is answered instead of redirected.
+///
+/// # Example
+/// ```rust
+/// let redirect = HttpRedirect::new(["example.com", "www.example.com"], 443);
+/// let serving = tokio::spawn(Arc::new(redirect).serve(sockets, shutdown, Duration::from_secs(2)));
+/// ```
+pub struct HttpRedirect {
+    /// Host names accepted in requests, and where they are redirected to.
+    canonical: CanonicalDomain,
+    /// Source of the key authorizations for HTTP-01 challenges, if they are answered here.
+    acme_challenges: Option<Arc<dyn Http01Challenges>>,
+}
+
+/// The pending ACME HTTP-01 challenges, implemented by the ACME certificate resolver.
+pub trait Http01Challenges: Send + Sync {
+    /// The key authorization answering the challenge for `token`, if it's pending.
+    fn key_authorization(&self, token: &str) -> Option<String>;
+}
+
+impl Http01Challenges for ResolvesServerCertAcme {
+    fn key_authorization(&self, token: &str) -> Option<String> {
+        self.get_http_01_key_auth(token)
+    }
+}
+
+impl HttpRedirect {
+    pub fn new(domains: impl IntoIterator<Item = impl Into<String>>, https_port: u16) -> Self {
+        Self {
+            canonical: CanonicalDomain::new(domains, https_port),
+            acme_challenges: None,
+        }
+    }
+
+    /// Answers ACME HTTP-01 challenges with the key authorizations held by `challenges`.
+    pub fn with_acme_challenges(mut self, challenges: Arc<dyn Http01Challenges>) -> Self {
+        self.acme_challenges = Some(challenges);
+        self
+    }
+
+    /// Accepts connections from `sockets` until `shutdown` resolves, answering each one on its
+    /// own task, then gives the open connections up to `grace` to finish.
+    pub async fn serve(
+        self: Arc<Self>,
+        sockets: Sockets,
+        shutdown: impl Future<Output = ()>,
+        grace: Duration,
+    ) {
+        let mut connections = JoinSet::new();
+        tokio::pin!(shutdown);
+
+        loop {
+            tokio::select! {
+                socket = sockets.accept() => {
+                    let Ok(socket) = socket else { break };
+                    let redirect = self.clone();
+                    connections.spawn(async move {
+                        if let Err(error) = redirect.handle(socket).await {
+                            log::debug!("HTTP redirect failed: {error}");
+                        }
+                    });
+                }
+                // Reap finished connections so the set doesn't grow forever:
+                Some(_) = connections.join_next() => {}
+                () = &mut shutdown => break,
+            }
+        }
+
+        // Stop accepting, anything still in the backlog is left to the next process:
+        drop(sockets);
+        if timeout(grace, connections.join_all()).await.is_err() {
+            log::warn!(
+                "Closed HTTP redirect connections still open after the shutdown grace period"
+            );
+        }
+    }
+
+    async fn handle(&self, mut stream: Socket) -> Result<()> {
+        let response = match timeout(TIMEOUT, read_request_head(&mut stream)).await {
+            Ok(Ok(head)) => self.respond(&String::from_utf8_lossy(&head)),
+            Ok(Err(error)) if error.kind() == ErrorKind::InvalidData => {
+                status_response("431 Request Header Fields Too Large")
+            }
+            Ok(Err(error)) => return Err(error),
+            Err(_) => status_response("408 Request Timeout"),
+        };
+
+        timeout(TIMEOUT, async {
+            stream.write_all(response.as_bytes()).await?;
+            stream.shutdown().await
+        })
+        .await
+        .map_err(|_| Error::from(ErrorKind::TimedOut))?
+    }
+
+    /// Builds the raw HTTP response for a request head.
+    fn respond(&self, head: &str) -> String {
+        let Some(request) = Request::parse(head) else {
+            return status_response("400 Bad Request");
+        };
+
+        let host_name = request
+            .host
+            .rsplit_once(':')
+            .filter(|(_, port)| port.bytes().all(|byte| byte.is_ascii_digit()))
+            .map_or(request.host, |(host_name, _)| host_name);
+        if !self
+            .canonical
+            .domains()
+            .iter()
+            .any(|domain| domain.eq_ignore_ascii_case(host_name))
+        {
+            return status_response("421 Misdirected Request");
+        }
+
+        if let Some(challenges) = &self.acme_challenges
+            && let Some(token) = request.target.strip_prefix(ACME_CHALLENGE_PATH)
+        {
+            return match challenges.key_authorization(token) {
+                Some(key_authorization) => format!(
+                    "HTTP/1.1 200 OK\r\n\
+                    Content-Type: application/octet-stream\r\n\
+                    Content-Length: {}\r\n\
+                    Connection: close\r\n\r\n\
+                    {key_authorization}",
+                    key_authorization.len()
+                ),
+                None => status_response("404 Not Found"),
+            };
+        }
+
+        let (status, location) = self.canonical.redirect(request.method, request.target);
+        format!(
+            "HTTP/1.1 {status}\r\n\
+            Location: {location}\r\n\
+            Content-Length: 0\r\n\
+            Connection: close\r\n\r\n"
+        )
+    }
+}
+
+/// The parts of an HTTP/1.x request head needed to redirect it.
+struct Request<'a> {
+    method: &'a str,
+    /// Path and query of the request (in origin-form, e.g. `/about?x=1`).
+    target: &'a str,
+    host: &'a str,
+}
+
+impl<'a> Request<'a> {
+    fn parse(head: &'a str) -> Option<Self> {
+        let mut lines = head.lines();
+        let mut request_line = lines.next()?.split(' ');
+        let (method, target, version) = (
+            request_line.next()?,
+            request_line.next()?,
+            request_line.next()?,
+        );
+        if request_line.next().is_some() || !version.starts_with("HTTP/1.") {
+            return None;
+        }
+
+        let host_header = lines.take_while(|line| !line.is_empty()).find_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            name.eq_ignore_ascii_case("host").then(|| value.trim())
+        });
+
+        // Absolute-form targets (`http://host/path`) take precedence over the `Host` header:
+        let (host, target) = match target.strip_prefix("http://") {
+            Some(absolute) => match absolute.find('/') {
+                Some(index) => (&absolute[..index], &absolute[index..]),
+                None => (absolute, "/"),
+            },
+            None => (host_header?, target),
+        };
+
+        let is_valid_target = target.starts_with('/')
+            && target
+                .bytes()
+                .all(|byte| byte.is_ascii_graphic() && byte != b'#');
+        (is_valid_target && !host.is_empty()).then_some(Self {
+            method,
+            target,
+            host,
+        })
+    }
+}
+
+/// Reads from `stream` until the blank line ending the request head.
+async fn read_request_head(stream: &mut Socket) -> Result<Vec<u8>> {
+    let mut head = Vec::with_capacity(1024);
+    let mut buffer = [0; 1024];
+
+    while !head.windows(4).any(|window| window == b"\r\n\r\n")
+        && !head.windows(2).any(|window| window == b"\n\n")
+    {
+        if head.len() >= MAX_REQUEST_HEAD_LENGTH {
+            return Err(Error::new(ErrorKind::InvalidData, "request head too long"));
+        }
+
+        let read = stream.read(&mut buffer).await?;
+        if read == 0 {
+            return Err(Error::from(ErrorKind::UnexpectedEof));
+        }
+        head.extend_from_slice(&buffer[..read]);
+    }
+
+    Ok(head)
+}
+
+fn status_response(status: &str) -> String {
+    format!(
+        "HTTP/1.1 {status}\r\n\
+        Content-Length: 0\r\n\
+        Connection: close\r\n\r\n"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::UnixStream,
+    };
+
+    use super::*;
+
+    fn redirect() -> HttpRedirect {
+        HttpRedirect::new(["example.com", "www.example.com"], 443)
+    }
+
+    /// The status line of a raw response.
+    fn status(response: &str) -> &str {
+        response.lines().next().unwrap()
+    }
+
+    fn location(response: &str) -> Option<&str> {
+        response
+            .lines()
+            .find_map(|line| line.strip_prefix("Location: "))
+    }
+
+    #[test]
+    fn requests_for_the_site_redirect_to_https() {
+        let response = redirect().respond("GET /about?lang=en HTTP/1.1\r\nHost: example.com\r\n");
+        assert_eq!(status(&response), "HTTP/1.1 301 Moved Permanently");
+        assert_eq!(
+            location(&response),
+            Some("https://example.com/about?lang=en")
+        );
+
+        // Alternate domains (with a port) go to the canonical one:
+        let response = redirect().respond("HEAD / HTTP/1.0\r\nhost: WWW.example.com:80\r\n");
+        assert_eq!(status(&response), "HTTP/1.1 301 Moved Permanently");
+        assert_eq!(location(&response), Some("https://example.com/"));
+
+        let response = HttpRedirect::new(["example.com"], 8443)
+            .respond("GET http://example.com/a?b HTTP/1.1\r\nHost: other.com\r\n");
+        assert_eq!(location(&response), Some("https://example.com:8443/a?b"));
+    }
+
+    #[test]
+    fn other_methods_keep_their_body() {
+        for method in ["POST", "PUT", "DELETE"] {
+            let response =
+                redirect().respond(&format!("{method} /form HTTP/1.1\r\nHost: example.com\r\n"));
+            assert_eq!(status(&response), "HTTP/1.1 308 Permanent Redirect");
+            assert_eq!(location(&response), Some("https://example.com/form"));
+        }
+    }
+
+    #[test]
+    fn bad_requests_are_not_redirected() {
+        for (head, expected) in [
+            ("GET / HTTP/1.1\r\n", "400 Bad Request"),
+            ("GET / HTTP/1.1\r\nHost: \r\n", "400 Bad Request"),
+            (
+                "GET about HTTP/1.1\r\nHost: example.com\r\n",
+                "400 Bad Request",
+            ),
+            ("GET / HTTP/2\r\nHost: example.com\r\n", "400 Bad Request"),
+            (
+                "GET / HTTP/1.1\r\nHost: evil.com\r\n",
+                "421 Misdirected Request",
+            ),
+            (
+                "GET / HTTP/1.1\r\nHost: example.com.evil.com\r\n",
+                "421 Misdirected Request",
+            ),
+        ] {
+            let response = redirect().respond(head);
+            assert_eq!(
+                status(&response),
+                format!("HTTP/1.1 {expected}"),
+                "{head:?}"
+            );
+            assert_eq!(location(&response), None);
+        }
+    }
+
+    impl Http01Challenges for HashMap<&'static str, &'static str> {
+        fn key_authorization(&self, token: &str) -> Option<String> {
+            self.get(token)
+                .map(|key_authorization| key_authorization.to_string())
+        }
+    }
+
+    #[test]
+    fn acme_challenges_are_answered_instead_of_redirected() {
+        let challenges = Arc::new(HashMap::from([("token", "token.thumbprint")]));
+        let answering = redirect().with_acme_challenges(challenges);
+
+        let response = answering
+            .respond("GET /.well-known/acme-challenge/token HTTP/1.1\r\nHost: example.com\r\n");
+        assert_eq!(status(&response), "HTTP/1.1 200 OK");
+        assert!(response.ends_with("\r\n\r\ntoken.thumbprint"));
+
+        let response = answering
+            .respond("GET /.well-known/acme-challenge/other HTTP/1.1\r\nHost: example.com\r\n");
+        assert_eq!(status(&response), "HTTP/1.1 404 Not Found");
+
+        // Only when enabled:
+        let response = redirect()
+            .respond("GET /.well-known/acme-challenge/token HTTP/1.1\r\nHost: example.com\r\n");
+        assert_eq!(status(&response), "HTTP/1.1 301 Moved Permanently");
+    }
+
+    #[tokio::test]
+    async fn oversized_request_heads_are_rejected() {
+        let (mut client, server) = UnixStream::pair().unwrap();
+        let handling = tokio::spawn(async move { redirect().handle(Socket::Unix(server)).await });
+
+        // Exactly the limit, without the blank line ending the head (anything more would be left
+        // unread, making the response a connection reset):
+        let mut head = b"GET / HTTP/1.1\r\nHost: example.com\r\nX-Padding: ".to_vec();
+        head.resize(MAX_REQUEST_HEAD_LENGTH, b'a');
+        client.write_all(&head).await.unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).await.unwrap();
+
+        assert_eq!(
+            status(&response),
+            "HTTP/1.1 431 Request Header Fields Too Large"
+        );
+        handling.await.unwrap().unwrap();
+    }
+}
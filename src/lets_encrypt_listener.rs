@@ -1,118 +1,263 @@
 use std::{
     fmt::Debug,
-    io::{Error, Result},
-    net::SocketAddr,
-    pin::Pin,
-    task::{Context, Poll},
+    io::{Error, ErrorKind, Result},
+    path::Path,
+    sync::{Arc, Mutex},
 };
 
+use auxv_dot_org::tls_status::TlsStatus;
 use rocket::listener::{Connection, Endpoint, Listener};
 use rustls_acme::{
-    AcmeConfig,
-    futures_rustls::server::TlsStream,
-    tokio::{TokioIncoming, TokioIncomingTcpWrapper},
+    AcmeConfig, AcmeState, EventError, EventOk, ResolvesServerCertAcme, is_tls_alpn_challenge,
 };
-use tokio::{
-    io::{AsyncRead, AsyncWrite, ReadBuf},
-    net::{TcpListener, TcpStream},
-    sync::Mutex,
+use tokio::io::AsyncWriteExt;
+use tokio_rustls::{
+    LazyConfigAcceptor,
+    rustls::{
+        ClientConfig, ClientConnection, RootCertStore, ServerConfig, ServerConnection,
+        crypto::ring,
+        pki_types::{CertificateDer, ServerName, pem::PemObject},
+        server::{Acceptor, ClientHello, ResolvesServerCert},
+        sign::CertifiedKey,
+    },
+    server::TlsStream,
+};
+use tokio_stream::StreamExt;
+
+use crate::{
+    sockets::{Socket, Sockets},
+    tls::{ALPN_PROTOCOLS, TlsConnection, certificate_expiry, complete_handshake},
 };
-use tokio_stream::{StreamExt, wrappers::TcpListenerStream};
-use tokio_util::compat::Compat;
 
 /// 🔐 A Rocket-compatible HTTPS listener that handles Let's Encrypt certificate automation.
 ///
-/// This listener wraps a TcpListener and manages automatic TLS certificate provisioning
+/// This listener wraps a set of listening [`Sockets`] and manages automatic TLS certificate provisioning
 /// and renewal through Let's Encrypt's ACME protocol. It implements Rocket's `Listener`
 /// trait to provide HTTPS connections while simultaneously completing TLS-ALPN-01 challenges.
+/// HTTP-01 challenges are answered elsewhere, through [`LetsEncryptListener::resolver`].
+///
+/// Certificate issuance, renewal and failures are logged and recorded in a [`TlsStatus`].
 ///
 /// # Example
 /// ```rust
-/// let tcp_listener = TcpListener::bind((Ipv4Addr::UNSPECIFIED, 443)).await?;
+/// let unix_permissions = UnixPermissions::default();
+/// let sockets = Sockets::bind(&["0.0.0.0".parse()?], 443, "https", &unix_permissions)?;
 /// let acme_config = AcmeConfig::new(["example.com"])
 ///     .contact(["mailto:admin@example.com"])
 ///     .directory_lets_encrypt(true);
 ///
-/// let https_listener = LetsEncryptListener::new(acme_config, tcp_listener).await;
+/// let https_listener = LetsEncryptListener::new(acme_config, sockets, tls_status).await;
 /// rocket.launch_on(https_listener).await?;
 /// ```
-pub struct LetsEncryptListener<T: Debug + 'static>(
-    Mutex<
-        TokioIncoming<
-            Compat<TcpStream>,
-            Error,
-            TokioIncomingTcpWrapper<TcpStream, Error, TcpListenerStream>,
-            T,
-            T,
-        >,
-    >,
-    SocketAddr,
-);
-
-impl<T: Debug + 'static> LetsEncryptListener<T> {
-    /// Makes a new `LetEncryptListener` from the given ACME configuration and TCP listener.
-    pub async fn new(acme_config: AcmeConfig<T, T>, tcp_listener: TcpListener) -> Self {
-        let local_address = tcp_listener.local_addr().unwrap();
-        let tcp_listener_stream = TcpListenerStream::new(tcp_listener);
-
-        Self(
-            Mutex::new(acme_config.tokio_incoming(tcp_listener_stream, Vec::new())),
-            local_address,
-        )
+pub struct LetsEncryptListener {
+    sockets: Sockets,
+    /// Used for TLS-ALPN-01 challenge handshakes, which end once the handshake is done.
+    challenge_rustls_config: Arc<ServerConfig>,
+    /// Used for everything else, serving the current certificate.
+    default_rustls_config: Arc<ServerConfig>,
+    resolver: Arc<ResolvesServerCertAcme>,
+}
+
+impl LetsEncryptListener {
+    /// Makes a new `LetEncryptListener` from the given ACME configuration and sockets.
+    ///
+    /// This spawns the task that orders and renews certificates in the background.
+    pub async fn new<T: Debug + 'static>(
+        acme_config: AcmeConfig<T, T>,
+        sockets: Sockets,
+        tls_status: Arc<TlsStatus>,
+    ) -> Self {
+        let state = acme_config.state();
+        let resolver = state.resolver();
+        let challenge_rustls_config = state.challenge_rustls_config();
+        let mut default_rustls_config =
+            ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+                .with_safe_default_protocol_versions()
+                .unwrap()
+                .with_no_client_auth()
+                .with_cert_resolver(Arc::new(StatusResolver {
+                    resolver: resolver.clone(),
+                    tls_status: tls_status.clone(),
+                    last_certified_key: Mutex::new(None),
+                }));
+
+        default_rustls_config.alpn_protocols =
+            ALPN_PROTOCOLS.map(|protocol| protocol.to_vec()).to_vec();
+        let default_rustls_config = Arc::new(default_rustls_config);
+
+        tokio::spawn(drive_acme_state(
+            state,
+            default_rustls_config.clone(),
+            tls_status,
+        ));
+
+        Self {
+            sockets,
+            challenge_rustls_config,
+            default_rustls_config,
+            resolver,
+        }
+    }
+
+    /// The certificate resolver, which also holds the key authorizations of pending HTTP-01
+    /// challenges.
+    pub fn resolver(&self) -> Arc<ResolvesServerCertAcme> {
+        self.resolver.clone()
+    }
+
+    /// Serves the current certificate, or answers a TLS-ALPN-01 challenge (which doesn't leave a
+    /// usable connection).
+    async fn handshake(&self, socket: Socket) -> Result<TlsStream<Socket>> {
+        let start_handshake = LazyConfigAcceptor::new(Acceptor::default(), socket).await?;
+
+        if is_tls_alpn_challenge(&start_handshake.client_hello()) {
+            let mut tls_stream = start_handshake
+                .into_stream(self.challenge_rustls_config.clone())
+                .await?;
+            tls_stream.shutdown().await?;
+            return Err(Error::new(
+                ErrorKind::ConnectionAborted,
+                "answered a TLS-ALPN-01 challenge",
+            ));
+        }
+
+        start_handshake
+            .into_stream(self.default_rustls_config.clone())
+            .await
     }
 }
 
-impl<T: Debug + 'static> Listener for LetsEncryptListener<T> {
-    type Accept = LetsEncryptConnection;
+impl Listener for LetsEncryptListener {
+    type Accept = Socket;
 
-    type Connection = Self::Accept;
+    type Connection = TlsConnection;
 
+    /// Accepts the next connection, the TLS handshake happens in [`Self::connect`], which Rocket
+    /// runs concurrently for each connection.
     async fn accept(&self) -> Result<Self::Accept> {
-        self.0
-            .lock()
-            .await
-            .next()
-            .await
-            .unwrap()
-            .map(|tls_stream| LetsEncryptConnection(tls_stream, self.1))
+        self.sockets.accept().await
     }
 
-    async fn connect(&self, accept: Self::Accept) -> Result<Self::Connection> {
-        Ok(accept)
+    /// Completes the TLS handshake, failures only drop this connection.
+    async fn connect(&self, socket: Self::Accept) -> Result<Self::Connection> {
+        let remote = socket.endpoint()?;
+        complete_handshake(remote, self.handshake(socket)).await
     }
 
     fn endpoint(&self) -> Result<Endpoint> {
-        Ok(Endpoint::Tcp(self.1))
+        self.sockets.endpoint()
     }
 }
 
-/// 🔐⬆️⬇️ A connection established through the Let's Encrypt listener.
-pub struct LetsEncryptConnection(Compat<TlsStream<Compat<TcpStream>>>, SocketAddr);
-
-impl AsyncWrite for LetsEncryptConnection {
-    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<Result<usize>> {
-        Pin::new(&mut self.get_mut().0).poll_write(cx, buf)
-    }
-    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
-        std::pin::Pin::new(&mut self.get_mut().0).poll_flush(cx)
+/// Orders and renews certificates for as long as the server runs, logging every ACME event and
+/// recording the expiry of each deployed certificate through `default_rustls_config`.
+async fn drive_acme_state<T: Debug + 'static>(
+    mut state: AcmeState<T, T>,
+    default_rustls_config: Arc<ServerConfig>,
+    tls_status: Arc<TlsStatus>,
+) {
+    while let Some(event) = state.next().await {
+        match event {
+            Ok(EventOk::DeployedNewCert) => {
+                log::info!("Deployed a newly issued certificate");
+                tls_status.renewal_succeeded();
+                resolve_current_certificate(default_rustls_config.clone());
+            }
+            Ok(EventOk::DeployedCachedCert) => {
+                log::info!("Deployed the cached certificate");
+                resolve_current_certificate(default_rustls_config.clone());
+            }
+            Ok(event) => log::debug!("ACME event: {event:?}"),
+            Err(EventError::Order(error)) => {
+                log::error!("Failed to order a certificate: {error}");
+                tls_status.renewal_failed(error.to_string());
+            }
+            Err(EventError::NewCertParse(error)) => {
+                log::error!("Failed to parse the newly issued certificate: {error}");
+                tls_status.renewal_failed(error.to_string());
+            }
+            Err(error) => {
+                log::error!("Let's Encrypt cache error: {error:?}");
+                tls_status.cache_failed();
+            }
+        }
     }
-    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
-        Pin::new(&mut self.get_mut().0).poll_shutdown(cx)
+
+    log::error!("Certificate management stopped, the current certificate won't be renewed");
+}
+
+/// Has `rustls_config` resolve its certificate for an in-memory `ClientHello`, so the
+/// [`StatusResolver`] records the expiry of a deployed certificate before any client connects
+/// (the ACME resolver only hands out its certificate during handshakes).
+fn resolve_current_certificate(rustls_config: Arc<ServerConfig>) {
+    let client_config = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_root_certificates(RootCertStore::empty())
+        .with_no_client_auth();
+    let server_name = ServerName::try_from("localhost").unwrap();
+    let (Ok(mut client), Ok(mut server)) = (
+        ClientConnection::new(Arc::new(client_config), server_name),
+        ServerConnection::new(rustls_config),
+    ) else {
+        return;
+    };
+
+    let mut client_hello = Vec::new();
+    if client.write_tls(&mut client_hello).is_ok()
+        && server.read_tls(&mut client_hello.as_slice()).is_ok()
+    {
+        // Resolving happens while the `ClientHello` is processed, the rest of the handshake
+        // doesn't matter:
+        let _ = server.process_new_packets();
     }
 }
 
-impl AsyncRead for LetsEncryptConnection {
-    fn poll_read(
-        self: Pin<&mut Self>,
-        cx: &mut Context<'_>,
-        buf: &mut ReadBuf<'_>,
-    ) -> Poll<Result<()>> {
-        Pin::new(&mut self.get_mut().0).poll_read(cx, buf)
+/// Serves the ACME resolver's certificate, recording its expiry whenever it changes (on
+/// deployment, see [`resolve_current_certificate`], with handshakes as a fallback).
+#[derive(Debug)]
+struct StatusResolver {
+    resolver: Arc<ResolvesServerCertAcme>,
+    tls_status: Arc<TlsStatus>,
+    last_certified_key: Mutex<Option<Arc<CertifiedKey>>>,
+}
+
+impl ResolvesServerCert for StatusResolver {
+    fn resolve(&self, client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        let certified_key = self.resolver.resolve(client_hello)?;
+
+        let mut last_certified_key = self.last_certified_key.lock().unwrap();
+        let is_new = !last_certified_key
+            .as_ref()
+            .is_some_and(|last| Arc::ptr_eq(last, &certified_key));
+        if is_new {
+            if let Some(expiry) = certificate_expiry(&certified_key) {
+                self.tls_status.set_certificate_expiry(expiry);
+            }
+            *last_certified_key = Some(certified_key.clone());
+        }
+
+        Some(certified_key)
     }
 }
 
-impl Connection for LetsEncryptConnection {
-    fn endpoint(&self) -> Result<Endpoint> {
-        Ok(Endpoint::Tcp(self.1))
+/// Builds the TLS configuration for talking to an ACME server whose certificate is issued by the
+/// given root (e.g. Pebble's `pebble.minica.pem`), rather than by a publicly trusted one.
+pub fn acme_client_tls_config(root_certificate: &Path) -> Result<Arc<ClientConfig>> {
+    let mut root_store = RootCertStore::empty();
+    for certificate in CertificateDer::pem_file_iter(root_certificate)
+        .map_err(|error| Error::new(ErrorKind::InvalidData, error))?
+    {
+        let certificate = certificate.map_err(|error| Error::new(ErrorKind::InvalidData, error))?;
+        root_store
+            .add(certificate)
+            .map_err(|error| Error::new(ErrorKind::InvalidData, error))?;
     }
+
+    let client_config = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .map_err(Error::other)?
+        .with_root_certificates(root_store)
+        .with_no_client_auth();
+    Ok(Arc::new(client_config))
 }
@@ -2,11 +2,50 @@
 extern crate rocket;
 
 pub mod api;
+pub mod assets;
+pub mod canonical_host;
+pub mod compression;
+pub mod config;
+pub mod content;
 pub mod emojis;
+pub mod export;
+mod git_dates;
+mod html;
 pub mod pages;
+pub mod tls_status;
 
-pub fn build_rocket() -> rocket::Rocket<rocket::Build> {
-    rocket::build()
-        .mount("/", routes![api::html_or_file, api::search])
-        .register("/", catchers![api::not_found])
+/// Builds the site's Rocket instance from the given configuration, serving the `site` rendered
+/// from it with [`pages::render_site`].
+pub fn build_rocket(
+    config: &config::SiteConfig,
+    site: pages::Site,
+) -> rocket::Rocket<rocket::Build> {
+    let mut rocket = rocket::build()
+        .manage(site)
+        .manage(config.content())
+        .manage(config.clone())
+        .manage(std::sync::Arc::new(tls_status::TlsStatus::default()))
+        .mount("/", routes![api::html_or_file])
+        .register("/", catchers![api::not_found]);
+
+    if config.features.search {
+        rocket = rocket.mount("/", routes![api::search]);
+    }
+
+    if config.status_token().is_some() {
+        rocket = rocket
+            .mount("/", routes![api::tls_status, api::metrics])
+            .register("/_status", catchers![api::unauthorized]);
+    }
+
+    if !config.http_only && config.domains.len() > 1 {
+        rocket = rocket
+            .attach(canonical_host::CanonicalHost::new(
+                &config.domains,
+                config.https_port,
+            ))
+            .mount("/", routes![canonical_host::redirect]);
+    }
+
+    rocket
 }
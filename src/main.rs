@@ -1,90 +1,319 @@
-use std::net::Ipv4Addr;
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
 
-use auxv_dot_org::{build_rocket, pages};
-use clap::Parser;
+use auxv_dot_org::{
+    build_rocket,
+    config::{AcmeChallenge, BindAddress, SiteConfig, TlsFiles},
+    export,
+    pages::{self, PageDates},
+    tls_status::TlsStatus,
+};
+use certificate_files_listener::CertificateFilesListener;
+use clap::{Parser, Subcommand};
+use http_redirect::HttpRedirect;
 use lets_encrypt_listener::LetsEncryptListener;
-use rocket::listener::tcp::TcpListener;
-use rustls_acme::{AcmeConfig, caches::DirCache};
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use rocket::{Ignite, Rocket};
+use rustls_acme::{AcmeConfig, UseChallenge, caches::DirCache};
+use sockets::{Sockets, UnixPermissions};
+use tokio::task::JoinHandle;
 
+mod certificate_files_listener;
+mod http_redirect;
 mod lets_encrypt_listener;
+mod privileges;
+mod sockets;
+mod tls;
 
 /// My personal markdown based webserver (though you are welcome to use it).
 #[derive(Parser)]
 #[command(version, about, propagate_version = true)]
 struct Args {
-    /// HTTP port to listen on
-    #[arg(long, default_value = "80")]
-    http_port: u16,
+    #[command(subcommand)]
+    command: Option<Command>,
 
-    /// HTTPS port to listen on
-    #[arg(long, default_value = "443")]
-    https_port: u16,
+    /// Site configuration file, the options below take precedence over it
+    #[arg(long, default_value = "site.toml")]
+    config: PathBuf,
 
-    /// Domain name for HTTPS certificate (required for HTTPS)
+    /// Address to listen on, may be repeated: an IPv4 or IPv6 address, or `unix:<directory>` for
+    /// `http.sock` and `https.sock` in that directory [default: 0.0.0.0]
     #[arg(long)]
-    domain: Option<String>,
+    bind: Vec<BindAddress>,
+
+    /// Permission bits (in octal) of the Unix sockets [default: 660]
+    #[arg(long, value_parser = parse_mode)]
+    unix_socket_mode: Option<u32>,
+
+    /// Group to hand the Unix sockets to, e.g. the reverse proxy's [default: the process's group]
+    #[arg(long)]
+    unix_socket_group: Option<String>,
+
+    /// HTTP port to listen on [default: 80]
+    #[arg(long)]
+    http_port: Option<u16>,
+
+    /// HTTPS port to listen on [default: 443]
+    #[arg(long)]
+    https_port: Option<u16>,
+
+    /// Domain name for HTTPS certificate, may be repeated with the first being canonical and the
+    /// rest redirecting to it (required for HTTPS)
+    #[arg(long = "domain")]
+    domains: Vec<String>,
 
     /// Let's Encrypt contact email (required for HTTPS)
     #[arg(long)]
     email: Option<String>,
 
-    /// Disable https (for testing without Let's Encrypt)
+    /// Disable https (for testing without Let's Encrypt), `--http-only=false` turns it back on
+    /// when the configuration file disables it
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    http_only: Option<bool>,
+
+    /// Directory to store Let's Encrypt cache [default: lets_encrypt_cache]
+    #[arg(long)]
+    lets_encrypt_cache: Option<PathBuf>,
+
+    /// User to switch to once the ports are bound, the Let's Encrypt cache is handed over to it
+    /// (TLS certificate files must be readable by it)
     #[arg(long)]
-    http_only: bool,
+    user: Option<String>,
+
+    /// Group to switch to along with `--user` [default: the user's primary group]
+    #[arg(long, requires = "user")]
+    group: Option<String>,
+
+    /// Use the Let's Encrypt staging environment (for testing without hitting rate limits),
+    /// `--lets-encrypt-staging=false` overrides the configuration file
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    lets_encrypt_staging: Option<bool>,
 
-    /// Directory to store Let's Encrypt cache
-    #[arg(long, default_value = "lets_encrypt_cache")]
-    lets_encrypt_cache: String,
+    /// Directory URL of an ACME server to use instead of Let's Encrypt
+    #[arg(long)]
+    acme_directory: Option<String>,
+
+    /// PEM root certificate to trust when connecting to the ACME server
+    #[arg(long)]
+    acme_root_certificate: Option<PathBuf>,
+
+    /// PEM certificate chain to serve instead of using Let's Encrypt (reloaded on change or
+    /// SIGHUP)
+    #[arg(long, requires = "tls_key")]
+    tls_cert: Option<PathBuf>,
+
+    /// PEM private key for `--tls-cert`
+    #[arg(long, requires = "tls_cert")]
+    tls_key: Option<PathBuf>,
+
+    /// Directory containing the site's pages (defaults to ./pages, or the embedded pages when
+    /// built with the `embed` feature)
+    #[arg(long)]
+    pages_dir: Option<PathBuf>,
+
+    /// Read page dates from git history (falls back to file modification times),
+    /// `--git-dates=false` overrides `render.dates = "git"` in the configuration file
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    git_dates: Option<bool>,
+}
+
+impl Args {
+    /// Applies the options given on the command line on top of the configuration file.
+    fn override_config(self, config: &mut SiteConfig) {
+        if !self.bind.is_empty() {
+            config.bind = self.bind;
+        }
+        if let Some(unix_socket_mode) = self.unix_socket_mode {
+            config.unix_socket_mode = unix_socket_mode;
+        }
+        if self.unix_socket_group.is_some() {
+            config.unix_socket_group = self.unix_socket_group;
+        }
+        if let Some(http_port) = self.http_port {
+            config.http_port = http_port;
+        }
+        if let Some(https_port) = self.https_port {
+            config.https_port = https_port;
+        }
+        if !self.domains.is_empty() {
+            config.domains = self.domains;
+        }
+        if self.email.is_some() {
+            config.email = self.email;
+        }
+        if let Some(http_only) = self.http_only {
+            config.http_only = http_only;
+        }
+        if let Some(lets_encrypt_cache) = self.lets_encrypt_cache {
+            config.lets_encrypt_cache = lets_encrypt_cache;
+        }
+        if self.user.is_some() {
+            config.user = self.user;
+        }
+        if self.group.is_some() {
+            config.group = self.group;
+        }
+        if let Some(lets_encrypt_staging) = self.lets_encrypt_staging {
+            config.lets_encrypt_staging = lets_encrypt_staging;
+        }
+        if self.acme_directory.is_some() {
+            config.acme_directory = self.acme_directory;
+        }
+        if self.acme_root_certificate.is_some() {
+            config.acme_root_certificate = self.acme_root_certificate;
+        }
+        if let (Some(cert), Some(key)) = (self.tls_cert, self.tls_key) {
+            config.tls = Some(TlsFiles { cert, key });
+        }
+        if self.pages_dir.is_some() {
+            config.pages_dir = self.pages_dir;
+        }
+        if let Some(git_dates) = self.git_dates {
+            config.render.dates = match git_dates {
+                true => PageDates::Git,
+                false => PageDates::Filesystem,
+            };
+        }
+    }
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Render every page into a directory that can be served by any static file host
+    Export {
+        /// Directory to write the site into
+        #[arg(default_value = "site")]
+        output: PathBuf,
+    },
 }
 
 #[rocket::main]
 async fn main() {
-    let args = Args::parse();
+    let mut args = Args::parse();
+    let command = args.command.take();
+
+    let mut config = SiteConfig::load(&args.config).unwrap();
+    args.override_config(&mut config);
+
+    let site = pages::render_site(&config).unwrap();
 
-    pages::set_page_cache().unwrap();
+    if let Some(Command::Export { output }) = command {
+        export::export_site(&site, &config.content(), &output).unwrap();
+        return;
+    }
 
-    let rocket = build_rocket();
+    let rocket = build_rocket(&config, site).ignite().await.unwrap();
 
-    let http_listener = TcpListener::bind((Ipv4Addr::UNSPECIFIED, args.http_port))
-        .await
-        .unwrap();
+    let http_listener = sockets(&config, config.http_port, "http");
 
-    if args.http_only {
+    if config.http_only {
+        drop_privileges(&config, &[]);
         rocket.launch_on(http_listener).await.unwrap();
-    } else {
+        return;
+    }
+
+    // Validate required HTTPS parameters:
+    assert!(
+        config.primary_domain().is_some(),
+        "Domain is required when using HTTPS"
+    );
+    let https_sockets = sockets(&config, config.https_port, "https");
+    std::fs::create_dir_all(&config.lets_encrypt_cache).unwrap();
+    drop_privileges(&config, &[&config.lets_encrypt_cache]);
+
+    if let Some(tls) = &config.tls {
+        // Enable HTTPS with the given certificate:
+        let tls_status = rocket.state::<Arc<TlsStatus>>().unwrap().clone();
+        let https_listener =
+            CertificateFilesListener::new(&tls.cert, &tls.key, https_sockets, tls_status)
+                .expect("Failed to load the TLS certificate");
+
         // HTTP Listener for redirection:
-        tokio::spawn(async move {
-            loop {
-                if let Ok((mut socket, _)) = http_listener.accept().await {
-                    tokio::spawn(async move {
-                        let mut buf = [0; 1024];
-                        if socket.read(&mut buf).await.is_ok() {
-                            // Simple HTTP 301 redirect response:
-                            let response = "HTTP/1.1 301 Moved Permanently\r\n\
-                                          Location: https://auxv.org\r\n\
-                                          Connection: close\r\n\r\n";
-                            let _ = socket.write_all(response.as_bytes()).await;
-                        }
-                    });
-                }
-            }
-        });
-
-        // Validate required HTTPS parameters:
-        let domain = args.domain.expect("Domain is required when using HTTPS");
-        let email = args.email.expect("Email is required when using HTTPS");
+        let http_redirect = HttpRedirect::new(&config.domains, config.https_port);
+        let redirecting = spawn_http_redirect(&rocket, http_redirect, http_listener);
+
+        rocket.launch_on(https_listener).await.unwrap();
+        redirecting.await.unwrap();
+    } else {
+        let email = config.email.expect("Email is required when using HTTPS");
 
         // Enable HTTPS via Let's Encrypt:
-        let tcp_listener = TcpListener::bind((Ipv4Addr::UNSPECIFIED, args.https_port))
-            .await
-            .unwrap();
-        let acme_config = AcmeConfig::new([domain])
+        let challenge_type = match config.acme_challenge {
+            AcmeChallenge::TlsAlpn01 => UseChallenge::TlsAlpn01,
+            AcmeChallenge::Http01 => UseChallenge::Http01,
+        };
+        // One certificate covering every domain (as subject alternative names):
+        let mut acme_config = AcmeConfig::new(&config.domains)
             .contact([format!("mailto:{}", email)])
-            .cache_option(Some(DirCache::new(args.lets_encrypt_cache)))
-            .directory_lets_encrypt(true);
+            .cache_option(Some(DirCache::new(config.lets_encrypt_cache)))
+            .challenge_type(challenge_type);
+        acme_config = match &config.acme_directory {
+            Some(directory) => acme_config.directory(directory),
+            None => acme_config.directory_lets_encrypt(!config.lets_encrypt_staging),
+        };
+        if let Some(root_certificate) = &config.acme_root_certificate {
+            let client_tls_config = lets_encrypt_listener::acme_client_tls_config(root_certificate)
+                .expect("Failed to load the ACME root certificate");
+            acme_config = acme_config.client_tls_config(client_tls_config);
+        }
+
+        let tls_status = rocket.state::<Arc<TlsStatus>>().unwrap().clone();
+        let https_listener = LetsEncryptListener::new(acme_config, https_sockets, tls_status).await;
+
+        // HTTP Listener for redirection (and HTTP-01 challenges):
+        let mut http_redirect = HttpRedirect::new(&config.domains, config.https_port);
+        if config.acme_challenge == AcmeChallenge::Http01 {
+            http_redirect = http_redirect.with_acme_challenges(https_listener.resolver());
+        }
+        let redirecting = spawn_http_redirect(&rocket, http_redirect, http_listener);
 
-        let https_listener = LetsEncryptListener::new(acme_config, tcp_listener).await;
         rocket.launch_on(https_listener).await.unwrap();
+        redirecting.await.unwrap();
+    }
+}
+
+/// Takes over the `name` sockets when started through systemd socket activation, otherwise binds
+/// `port` on the configured addresses.
+fn sockets(config: &SiteConfig, port: u16, name: &str) -> Sockets {
+    let unix_permissions = UnixPermissions {
+        mode: config.unix_socket_mode,
+        group: config
+            .unix_socket_group
+            .as_deref()
+            .map(privileges::group_id)
+            .transpose()
+            .expect("Failed to look up the Unix socket group"),
+    };
+
+    Sockets::from_systemd(name)
+        .transpose()
+        .unwrap_or_else(|| Sockets::bind(&config.bind, port, name, &unix_permissions))
+        .unwrap_or_else(|error| panic!("Failed to listen for {name}: {error}"))
+}
+
+/// Parses an octal permission mode, with or without a `0o` prefix.
+fn parse_mode(mode: &str) -> Result<u32, String> {
+    u32::from_str_radix(mode.trim_start_matches("0o"), 8)
+        .map_err(|_| format!("{mode:?} isn't an octal mode like 660"))
+}
+
+/// Serves `http_redirect` alongside Rocket, shutting down with it (and given the same grace
+/// period to finish open connections).
+fn spawn_http_redirect(
+    rocket: &Rocket<Ignite>,
+    http_redirect: HttpRedirect,
+    sockets: Sockets,
+) -> JoinHandle<()> {
+    let grace = Duration::from_secs(rocket.config().shutdown.grace.into());
+    tokio::spawn(Arc::new(http_redirect).serve(sockets, rocket.shutdown(), grace))
+}
+
+/// Switches to the configured user and group, if any, handing `writable` over to them.
+fn drop_privileges(config: &SiteConfig, writable: &[&Path]) {
+    if let Some(user) = &config.user {
+        privileges::drop_privileges(user, config.group.as_deref(), writable)
+            .expect("Failed to drop privileges");
     }
 }
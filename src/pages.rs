@@ -1,75 +1,337 @@
-#![allow(static_mut_refs)]
-
 use std::{
-    collections::{HashMap, VecDeque},
-    fs::{read_dir, read_to_string},
-    mem::MaybeUninit,
+    collections::HashMap,
     path::{Path, PathBuf},
+    time::SystemTime,
 };
 
 use pulldown_cmark::{Event, Options, Parser, Tag, TagEnd};
+use rocket::serde::Deserialize;
+
+use crate::{
+    assets::{self, Fingerprints},
+    compression::Representation,
+    config::{Metadata, Render, SiteConfig},
+    emojis::{EmojiParser, EmojiSprite},
+    html::{HtmlContext, ends_block, escape_html},
+};
 
-use crate::emojis::EmojiParser;
+/// Average adult silent reading speed used for the reading time estimate.
+const WORDS_PER_MINUTE: usize = 200;
 
-pub const PAGE_CACHE_DIR: &Path = unsafe { std::mem::transmute("./pages") };
-static mut PAGE_CACHE: MaybeUninit<HashMap<&'static Path, &'static str>> = MaybeUninit::uninit();
+/// A rendered page along with the metadata collected while rendering it.
+#[derive(Clone, Copy)]
+pub struct Page {
+    /// The rendered HTML, compressed while rendering.
+    pub html: Representation,
+    /// The (escaped) text of the `<title>` in the page's head, including the configured default.
+    pub title: Option<&'static str>,
+    pub word_count: usize,
+    /// Estimated reading time in whole minutes (never less than one).
+    pub reading_time: usize,
+    pub created: SystemTime,
+    pub last_modified: SystemTime,
+    /// `:shortcode:`s in the markdown that aren't an emoji's (left as they are), or whose emoji has
+    /// no SVG (left as a character).
+    pub unknown_shortcodes: &'static [String],
+}
 
-pub fn get_page_cache() -> &'static HashMap<&'static Path, &'static str> {
-    unsafe { PAGE_CACHE.assume_init_ref() }
+/// Where the created and last modified dates of each page come from.
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(crate = "rocket::serde", rename_all = "lowercase")]
+pub enum PageDates {
+    /// File system timestamps of the markdown files.
+    Filesystem,
+    /// Commit history of the git repository containing the pages, falling back to the file system
+    /// for files git doesn't know about (e.g. uncommitted ones).
+    Git,
 }
 
-pub fn set_page_cache() -> Result<(), std::io::Error> {
-    let template_html = read_to_string(Path::new(PAGE_CACHE_DIR).join("templates/template.html"))?;
-    let emoji_parser = EmojiParser::new(Path::new(PAGE_CACHE_DIR).join("emojis"))?;
-    let markdown_options = Options::empty()
-        | Options::ENABLE_TABLES
-        | Options::ENABLE_STRIKETHROUGH
-        | Options::ENABLE_HEADING_ATTRIBUTES;
+/// Everything rendered from the `pages/` tree, managed by Rocket, see [`render_site`].
+pub struct Site {
+    /// Every page by URL path (e.g. `projects/franxx` for `projects/franxx.md`), directories also
+    /// having their `index` page.
+    pub pages: HashMap<PathBuf, Page>,
+    /// The fingerprinted URLs the pages link to.
+    pub fingerprints: Fingerprints,
+    /// The sprite sheet of every emoji on the pages in
+    /// [`EmojiMode::Sprite`](crate::emojis::EmojiMode::Sprite).
+    pub emoji_sprite: Option<EmojiSprite>,
+}
 
-    let mut pages = HashMap::new();
+impl Site {
+    /// The current fingerprinted path of a file whose fingerprinted `path` has another hash, as
+    /// linked by pages rendered before the file changed (e.g. cached from before a deploy).
+    pub fn current_fingerprint(&self, path: &Path) -> Option<PathBuf> {
+        let is_current = self.fingerprints.original(path).is_some()
+            || self
+                .emoji_sprite
+                .as_ref()
+                .is_some_and(|sprite| sprite.path == path);
+        if is_current {
+            return None;
+        }
+
+        let original = assets::unfingerprinted(path)?;
+        match &self.emoji_sprite {
+            Some(sprite) if sprite.original == original => Some(sprite.path.clone()),
+            _ => self
+                .fingerprints
+                .fingerprinted(&original)
+                .map(Path::to_path_buf),
+        }
+    }
+}
+
+/// Renders every markdown page of the configured content into its template.
+pub fn render_site(config: &SiteConfig) -> Result<Site, std::io::Error> {
+    let content = config.content();
+    let template_html = content.read_to_string(&config.render.template)?;
+    let emoji_parser = match config.features.emojis {
+        true => Some(EmojiParser::new(
+            content.clone(),
+            config.render.emoji_directory.clone(),
+            config.render.emoji_mode,
+        )?),
+        false => None,
+    };
+    let markdown_options = markdown_options(&config.render);
+    let mut fingerprints = match config.features.fingerprints {
+        true => Fingerprints::new(&content)?,
+        false => Fingerprints::none()?,
+    };
 
-    for path in read_dir_all(PAGE_CACHE_DIR)
-        .unwrap()
-        .filter_map(|entry| entry.ok())
+    let git_dates = match config.render.dates {
+        PageDates::Git => content.git_dates().unwrap_or_else(|error| {
+            log::warn!("Falling back to file system dates, could not read git history: {error}");
+            HashMap::new()
+        }),
+        PageDates::Filesystem => HashMap::new(),
+    };
+
+    let mut rendered_pages = Vec::new();
+
+    for path in content
+        .read_dir_all("")?
+        .into_iter()
         .filter(|path| path.extension().map_or(false, |ext| ext == "md"))
     {
-        let page = read_to_string(&path)?;
+        let page = content.read_to_string(&path)?;
+        let (created, last_modified) = match git_dates.get(&path) {
+            Some(&dates) => dates,
+            None => content.dates(&path)?,
+        };
         let (head, markdown) = parse_head(&page).unwrap_or(("", &page));
-        let url: &'static Path = Box::leak(
-            path.strip_prefix(PAGE_CACHE_DIR)
-                .unwrap()
-                .with_extension("")
-                .into_boxed_path(),
-        );
-
-        let markdown_parser = generate_heading_slugs(Parser::new_ext(&markdown, markdown_options));
+        let head = with_default_metadata(head, &config.metadata);
+        let url = path.with_extension("");
+
+        let markdown_events = generate_heading_slugs(Parser::new_ext(&markdown, markdown_options));
+        let word_count = count_words(&markdown_events);
+        let reading_time = word_count.div_ceil(WORDS_PER_MINUTE).max(1);
+
+        let (markdown_events, unknown_shortcodes) = match &emoji_parser {
+            Some(emoji_parser) => {
+                let events =
+                    emoji_parser.substitute(markdown_events, wants_native_emojis(&head))?;
+                (events, emoji_parser.take_unknown_shortcodes())
+            }
+            None => (markdown_events, Vec::new()),
+        };
+        for shortcode in &unknown_shortcodes {
+            log::warn!(
+                "Emoji shortcode :{shortcode}: in {} is unknown or has no SVG",
+                path.display()
+            );
+        }
+
         let mut markdown_as_html = String::new();
-        pulldown_cmark::html::push_html(&mut markdown_as_html, markdown_parser.into_iter());
+        pulldown_cmark::html::push_html(&mut markdown_as_html, markdown_events.into_iter());
 
-        let emoji_substitute_markdown_as_html =
-            emoji_parser.inline_from_directory(&markdown_as_html);
+        let rendered_html = fingerprints.rewrite(
+            &content,
+            &fill_template(
+                &template_html,
+                &[
+                    ("html", &markdown_as_html),
+                    ("head", &head),
+                    ("word_count", &word_count.to_string()),
+                    ("reading_time", &reading_time.to_string()),
+                    ("created", &format_date(created)),
+                    ("last_modified", &format_date(last_modified)),
+                ],
+            ),
+        )?;
 
-        let rendered_html = template_html
-            .clone()
-            .replace("{{html}}", &emoji_substitute_markdown_as_html)
-            .replace("{{head}}", &head);
+        rendered_pages.push(RenderedPage {
+            url,
+            html: rendered_html,
+            title: head_title(&head).map(str::to_owned),
+            unknown_shortcodes,
+            word_count,
+            reading_time,
+            created,
+            last_modified,
+        });
+    }
 
-        let leaked_html: &'static str = Box::leak(rendered_html.into_boxed_str());
+    // Only complete once every page has been rendered:
+    let emoji_sprite = emoji_parser
+        .map(|parser| parser.sprite())
+        .transpose()?
+        .flatten();
 
-        pages.insert(url, leaked_html);
+    let mut pages = HashMap::new();
+    for rendered_page in rendered_pages {
+        let html = match &emoji_sprite {
+            Some(emoji_sprite) => emoji_sprite.link(&rendered_page.html),
+            None => rendered_page.html,
+        };
+        let url = rendered_page.url;
+        let page = Page {
+            html: Representation::new(html)?,
+            title: rendered_page.title.map(|title| title.leak() as &str),
+            unknown_shortcodes: rendered_page.unknown_shortcodes.leak(),
+            word_count: rendered_page.word_count,
+            reading_time: rendered_page.reading_time,
+            created: rendered_page.created,
+            last_modified: rendered_page.last_modified,
+        };
 
         if url.file_name().map_or(false, |name| name == "index") {
             if let Some(directory_url) = url.parent() {
-                pages.insert(directory_url, leaked_html);
+                pages.insert(directory_url.to_path_buf(), page);
             }
         }
+
+        pages.insert(url, page);
     }
 
-    unsafe {
-        PAGE_CACHE.write(pages);
+    Ok(Site {
+        pages,
+        fingerprints,
+        emoji_sprite,
+    })
+}
+
+/// A page rendered into its template, compressed into a [`Page`] once the URL of the emoji sprite
+/// sheet is known.
+struct RenderedPage {
+    url: PathBuf,
+    html: String,
+    title: Option<String>,
+    unknown_shortcodes: Vec<String>,
+    word_count: usize,
+    reading_time: usize,
+    created: SystemTime,
+    last_modified: SystemTime,
+}
+
+fn markdown_options(render: &Render) -> Options {
+    [
+        (render.tables, Options::ENABLE_TABLES),
+        (render.strikethrough, Options::ENABLE_STRIKETHROUGH),
+        (
+            render.heading_attributes,
+            Options::ENABLE_HEADING_ATTRIBUTES,
+        ),
+        (render.footnotes, Options::ENABLE_FOOTNOTES),
+        (render.task_lists, Options::ENABLE_TASKLISTS),
+        (render.smart_punctuation, Options::ENABLE_SMART_PUNCTUATION),
+    ]
+    .into_iter()
+    .filter(|(enabled, _)| *enabled)
+    .fold(Options::empty(), |options, (_, option)| options | option)
+}
+
+/// Replaces the `{{name}}` placeholders in `template` with their values in a single pass, so
+/// placeholders in the values (e.g. a page documenting the template) are left as they are.
+fn fill_template(template: &str, values: &[(&str, &str)]) -> String {
+    let mut filled = String::with_capacity(template.len());
+    let mut remaining = template;
+    while let Some(start) = remaining.find("{{") {
+        filled.push_str(&remaining[..start]);
+        remaining = &remaining[start..];
+        let value = values.iter().find_map(|(name, value)| {
+            let placeholder = remaining.strip_prefix("{{")?.strip_prefix(name)?;
+            Some((*value, placeholder.strip_prefix("}}")?))
+        });
+        match value {
+            Some((value, after)) => {
+                filled.push_str(value);
+                remaining = after;
+            }
+            None => {
+                filled.push_str("{{");
+                remaining = &remaining["{{".len()..];
+            }
+        }
     }
+    filled.push_str(remaining);
+    filled
+}
 
-    Ok(())
+/// Appends the configured default `<title>`, author and description to a page's head, unless the
+/// page already sets them itself.
+fn with_default_metadata(head: &str, metadata: &Metadata) -> String {
+    let mut head = head.to_owned();
+    if let Some(title) = metadata
+        .title
+        .as_ref()
+        .filter(|_| !head.contains("<title>"))
+    {
+        head.push_str(&format!("\n<title>{}</title>", escape_html(title)));
+    }
+    for (name, value) in [
+        ("author", &metadata.author),
+        ("description", &metadata.description),
+    ] {
+        if let Some(value) = value
+            .as_ref()
+            .filter(|_| !head.contains(&format!("name=\"{name}\"")))
+        {
+            head.push_str(&format!(
+                "\n<meta name=\"{name}\" content=\"{}\">",
+                escape_html(value)
+            ));
+        }
+    }
+    head
+}
+
+/// The text of the first `<title>` in a page's head, which (unlike a `<title>` in the rendered
+/// HTML) can't be an emoji's accessible name.
+fn head_title(head: &str) -> Option<&str> {
+    let (_, title) = head.split_once("<title>")?;
+    let (title, _) = title.split_once("</title>")?;
+    Some(title.trim())
+}
+
+/// Hex encoded SHA-256 of `bytes`, truncated to 128 bits.
+pub fn content_hash(bytes: &[u8]) -> String {
+    ring::digest::digest(&ring::digest::SHA256, bytes).as_ref()[..16]
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+/// Whether a page's head has `<meta name="emojis" content="native">` (in any case, with the
+/// attributes in either order and quoted or not), which leaves its emoji as characters rather than
+/// substituting SVGs.
+fn wants_native_emojis(head: &str) -> bool {
+    let head = head.to_ascii_lowercase();
+    head.split("<meta").skip(1).any(|tag| {
+        let tag = &tag[..tag.find('>').unwrap_or(tag.len())];
+        let attributes: Vec<_> = tag
+            .split(|c: char| c.is_ascii_whitespace() || c == '/')
+            .filter_map(|attribute| attribute.split_once('='))
+            .map(|(name, value)| (name, value.trim_matches(['"', '\''])))
+            .collect();
+        attributes.contains(&("name", "emojis")) && attributes.contains(&("content", "native"))
+    })
+}
+
+/// Formats a timestamp as an ISO 8601 calendar date (e.g. `2025-01-31`).
+fn format_date(time: SystemTime) -> String {
+    time::OffsetDateTime::from(time).date().to_string()
 }
 
 fn parse_head(markdown: &str) -> Option<(&str, &str)> {
@@ -86,29 +348,29 @@ fn parse_head(markdown: &str) -> Option<(&str, &str)> {
     Some((head, remaining))
 }
 
-fn read_dir_all(
-    directory: impl AsRef<Path>,
-) -> std::io::Result<impl Iterator<Item = std::io::Result<PathBuf>>> {
-    let mut queue = VecDeque::new();
-    queue.extend(read_dir(directory)?);
-
-    Ok(std::iter::from_fn(move || {
-        while let Some(entry) = queue.pop_front() {
-            let path = match entry {
-                Ok(v) => v.path(),
-                Err(e) => return Some(Err(e)),
-            };
-            if path.is_dir() {
-                queue.extend(match read_dir(path) {
-                    Ok(v) => v,
-                    Err(e) => return Some(Err(e)),
-                });
-                continue;
+/// Counts the words of prose in a page, skipping code blocks and raw HTML elements that don't hold
+/// prose (e.g. inline SVG diagrams), see [`VERBATIM_ELEMENTS`](crate::html::VERBATIM_ELEMENTS).
+fn count_words(events: &[Event]) -> usize {
+    let mut in_code_block = false;
+    let mut html = HtmlContext::default();
+    let mut word_count = 0;
+
+    for event in events {
+        match event {
+            Event::Start(Tag::CodeBlock(_)) => in_code_block = true,
+            Event::End(TagEnd::CodeBlock) => in_code_block = false,
+            Event::End(tag) if ends_block(tag) => html = HtmlContext::default(),
+            Event::Html(raw) | Event::InlineHtml(raw) => html.scan(raw),
+            Event::Text(text) | Event::Code(text)
+                if !in_code_block && !html.in_verbatim_element() =>
+            {
+                word_count += text.split_whitespace().count();
             }
-            return Some(Ok(path));
+            _ => {}
         }
-        None
-    }))
+    }
+
+    word_count
 }
 
 fn generate_heading_slugs<'a>(parser: impl Iterator<Item = Event<'a>>) -> Vec<Event<'a>> {
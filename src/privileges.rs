@@ -0,0 +1,102 @@
+use std::{
+    ffi::CString,
+    io::{Error, ErrorKind, Result},
+    os::unix::fs::lchown,
+    path::Path,
+};
+
+/// 👤 Switches the process to an unprivileged `user` (and `group`, otherwise the user's primary
+/// group), meant to be called once every privileged port is bound.
+///
+/// The `writable` paths (and everything below them) are handed over to the user first, so they
+/// stay writable afterwards.
+///
+/// # Example
+/// ```rust
+/// drop_privileges("www-data", None, &[Path::new("lets_encrypt_cache")])?;
+/// ```
+pub fn drop_privileges(user: &str, group: Option<&str>, writable: &[&Path]) -> Result<()> {
+    let user_name = CString::new(user)?;
+    // Only called during startup, so nothing else is using `getpwnam`'s static buffer:
+    let passwd = unsafe { libc::getpwnam(user_name.as_ptr()) };
+    if passwd.is_null() {
+        return Err(Error::new(
+            ErrorKind::NotFound,
+            format!("There is no user named {user}"),
+        ));
+    }
+    let (uid, mut gid) = unsafe { ((*passwd).pw_uid, (*passwd).pw_gid) };
+
+    if let Some(group) = group {
+        gid = group_id(group)?;
+    }
+
+    for path in writable {
+        chown_all(path, uid, gid)?;
+    }
+
+    // The supplementary groups and group have to go first, changing them needs root:
+    if unsafe { libc::setgroups(0, std::ptr::null()) } != 0
+        || unsafe { libc::setgid(gid) } != 0
+        || unsafe { libc::setuid(uid) } != 0
+    {
+        return Err(Error::last_os_error());
+    }
+
+    log::info!("Dropped privileges to user {user} ({uid}) and group {gid}");
+    Ok(())
+}
+
+/// Looks up the ID of the group named `group`.
+pub fn group_id(group: &str) -> Result<libc::gid_t> {
+    let group_name = CString::new(group)?;
+    // Only called during startup, so nothing else is using `getgrnam`'s static buffer:
+    let group_entry = unsafe { libc::getgrnam(group_name.as_ptr()) };
+    if group_entry.is_null() {
+        return Err(Error::new(
+            ErrorKind::NotFound,
+            format!("There is no group named {group}"),
+        ));
+    }
+    Ok(unsafe { (*group_entry).gr_gid })
+}
+
+/// Recursively changes the owner of `path`, without following symlinks.
+fn chown_all(path: &Path, uid: libc::uid_t, gid: libc::gid_t) -> Result<()> {
+    lchown(path, Some(uid), Some(gid))?;
+
+    if std::fs::symlink_metadata(path)?.is_dir() {
+        for entry in std::fs::read_dir(path)? {
+            chown_all(&entry?.path(), uid, gid)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Each of these fails before anything is changed, so they are safe to run as root.
+
+    #[test]
+    fn unknown_users_are_rejected() {
+        let error = drop_privileges("auxv-no-such-user", None, &[]).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn unknown_groups_are_rejected() {
+        let error = drop_privileges("root", Some("auxv-no-such-group"), &[]).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn names_with_nul_bytes_are_rejected() {
+        let error = drop_privileges("root\0", None, &[]).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        let error = drop_privileges("root", Some("root\0"), &[]).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+    }
+}
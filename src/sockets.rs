@@ -0,0 +1,328 @@
+use std::{
+    future::poll_fn,
+    io::{Error, ErrorKind, Result},
+    net::SocketAddr,
+    os::{
+        fd::{FromRawFd, OwnedFd, RawFd},
+        unix::fs::{FileTypeExt, PermissionsExt, lchown},
+    },
+    path::Path,
+    pin::Pin,
+    sync::atomic::{AtomicUsize, Ordering},
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use auxv_dot_org::config::BindAddress;
+use rocket::listener::{Connection, Endpoint, Listener};
+use socket2::{Domain, Socket as RawSocket, Type};
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    net::{TcpListener, TcpStream, UnixListener, UnixStream},
+    time::sleep,
+};
+
+/// The first file descriptor passed by systemd socket activation, see `sd_listen_fds(3)`.
+const SD_LISTEN_FDS_START: RawFd = 3;
+
+/// 🔌 A set of listening TCP and Unix sockets, accepted from as one Rocket `Listener`.
+///
+/// Connections are only accepted when asked for, so any that haven't been are left in the
+/// kernel's backlog; with socket activation they are picked up by the next process. Accept
+/// errors (usually running out of file descriptors) are logged and retried rather than ending
+/// the server.
+///
+/// # Example
+/// ```rust
+/// let addresses = ["0.0.0.0".parse()?, "::".parse()?, "unix:/run/auxv".parse()?];
+/// // Binds 0.0.0.0:80, [::]:80 and /run/auxv/http.sock:
+/// let sockets = Sockets::bind(&addresses, 80, "http", &UnixPermissions::default())?;
+/// rocket.launch_on(sockets).await?;
+/// ```
+pub struct Sockets {
+    listeners: Vec<Bound>,
+    /// Which listener is polled first, rotated so a busy one can't starve the others.
+    next: AtomicUsize,
+}
+
+impl Sockets {
+    /// Binds `port` on each IP address, and `<name>.sock` in each Unix socket directory.
+    pub fn bind(
+        addresses: &[BindAddress],
+        port: u16,
+        name: &str,
+        unix_permissions: &UnixPermissions,
+    ) -> Result<Self> {
+        assert!(
+            !addresses.is_empty(),
+            "At least one bind address is required"
+        );
+
+        let listeners = addresses
+            .iter()
+            .map(|address| match address {
+                BindAddress::Ip(ip) => bind_tcp(SocketAddr::new(*ip, port)).map(Bound::Tcp),
+                BindAddress::Unix(directory) => {
+                    bind_unix(&directory.join(format!("{name}.sock")), unix_permissions)
+                        .map(Bound::Unix)
+                }
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self::new(listeners))
+    }
+
+    /// Takes over the sockets named `name` (with `FileDescriptorName=` in the `.socket` unit)
+    /// when started through systemd socket activation, `None` when there are none.
+    ///
+    /// systemd keeps these open while the service restarts, so connections made in the meantime
+    /// wait for the new process instead of being refused.
+    pub fn from_systemd(name: &str) -> Result<Option<Self>> {
+        let is_activated =
+            std::env::var("LISTEN_PID").is_ok_and(|pid| pid.parse() == Ok(std::process::id()));
+        if !is_activated {
+            return Ok(None);
+        }
+
+        let count: RawFd = std::env::var("LISTEN_FDS")
+            .ok()
+            .and_then(|count| count.parse().ok())
+            .unwrap_or(0);
+        let names = std::env::var("LISTEN_FDNAMES").unwrap_or_default();
+
+        let listeners: Vec<Bound> = (SD_LISTEN_FDS_START..SD_LISTEN_FDS_START + count)
+            .zip(names.split(':'))
+            .filter(|(_, fd_name)| *fd_name == name)
+            .map(|(fd, _)| {
+                // systemd hands these descriptors to us, nothing else in the process owns them:
+                let socket = RawSocket::from(unsafe { OwnedFd::from_raw_fd(fd) });
+                socket.set_nonblocking(true)?;
+                match socket.local_addr()?.is_unix() {
+                    true => UnixListener::from_std(OwnedFd::from(socket).into()).map(Bound::Unix),
+                    false => TcpListener::from_std(socket.into()).map(Bound::Tcp),
+                }
+            })
+            .collect::<Result<_>>()?;
+
+        Ok((!listeners.is_empty()).then(|| Self::new(listeners)))
+    }
+
+    fn new(listeners: Vec<Bound>) -> Self {
+        Self {
+            listeners,
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    /// Waits for a connection on any of the sockets.
+    ///
+    /// Only one task should be accepting at a time, the others wouldn't be woken up.
+    pub async fn accept(&self) -> Result<Socket> {
+        loop {
+            match poll_fn(|cx| self.poll_accept(cx)).await {
+                Ok(socket) => return Ok(socket),
+                Err(error) => {
+                    // Usually out of file descriptors, give some connections a chance to close:
+                    log::warn!("Failed to accept a connection: {error}");
+                    sleep(Duration::from_millis(100)).await;
+                }
+            }
+        }
+    }
+
+    fn poll_accept(&self, cx: &mut Context<'_>) -> Poll<Result<Socket>> {
+        let first = self.next.fetch_add(1, Ordering::Relaxed);
+        for index in 0..self.listeners.len() {
+            let polled = match &self.listeners[(first + index) % self.listeners.len()] {
+                Bound::Tcp(listener) => listener
+                    .poll_accept(cx)
+                    .map_ok(|(stream, _)| Socket::Tcp(stream)),
+                Bound::Unix(listener) => listener
+                    .poll_accept(cx)
+                    .map_ok(|(stream, _)| Socket::Unix(stream)),
+            };
+            if polled.is_ready() {
+                return polled;
+            }
+        }
+        Poll::Pending
+    }
+}
+
+impl Listener for Sockets {
+    type Accept = Socket;
+
+    type Connection = Socket;
+
+    async fn accept(&self) -> Result<Self::Accept> {
+        Sockets::accept(self).await
+    }
+
+    async fn connect(&self, accept: Self::Accept) -> Result<Self::Connection> {
+        Ok(accept)
+    }
+
+    /// The first socket, which Rocket reports as the address it launched on.
+    fn endpoint(&self) -> Result<Endpoint> {
+        match &self.listeners[0] {
+            Bound::Tcp(listener) => listener.local_addr().map(Endpoint::Tcp),
+            Bound::Unix(listener) => Ok(Endpoint::Unix(
+                listener
+                    .local_addr()?
+                    .as_pathname()
+                    .map(Path::to_path_buf)
+                    .unwrap_or_default(),
+            )),
+        }
+    }
+}
+
+/// Who may connect to the Unix sockets [`Sockets::bind`] creates, set once they are bound.
+///
+/// Sockets passed by systemd are left alone, they get the `.socket` unit's `SocketMode=` and
+/// `SocketGroup=`.
+#[derive(Clone, Copy, Debug)]
+pub struct UnixPermissions {
+    /// Permission bits, connecting needs write permission.
+    pub mode: u32,
+    /// Group to hand the sockets to (e.g. the reverse proxy's), otherwise the process's group.
+    pub group: Option<libc::gid_t>,
+}
+
+impl Default for UnixPermissions {
+    fn default() -> Self {
+        Self {
+            mode: 0o660,
+            group: None,
+        }
+    }
+}
+
+/// A listening socket.
+enum Bound {
+    Tcp(TcpListener),
+    Unix(UnixListener),
+}
+
+/// Binds a TCP socket, IPv6 sockets only accept IPv6 so `0.0.0.0` and `::` can both be bound.
+fn bind_tcp(address: SocketAddr) -> Result<TcpListener> {
+    let socket = RawSocket::new(Domain::for_address(address), Type::STREAM, None)?;
+    if address.is_ipv6() {
+        socket.set_only_v6(true)?;
+    }
+    socket.set_reuse_address(true)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&address.into())?;
+    socket.listen(1024)?;
+
+    TcpListener::from_std(socket.into())
+}
+
+/// Binds a Unix socket, replacing the one left behind by a previous run.
+fn bind_unix(path: &Path, permissions: &UnixPermissions) -> Result<UnixListener> {
+    match std::fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => std::fs::remove_file(path)?,
+        Ok(_) => {
+            return Err(Error::new(
+                ErrorKind::AlreadyExists,
+                format!("{} exists and isn't a socket", path.display()),
+            ));
+        }
+        Err(_) => {}
+    }
+
+    let listener = UnixListener::bind(path)?;
+    // The umask decides the mode the socket is created with, so it is set explicitly instead:
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(permissions.mode))?;
+    if let Some(group) = permissions.group {
+        lchown(path, None, Some(group))?;
+    }
+
+    Ok(listener)
+}
+
+/// ⬆️⬇️ A connection accepted from one of the [`Sockets`].
+pub enum Socket {
+    Tcp(TcpStream),
+    Unix(UnixStream),
+}
+
+impl AsyncWrite for Socket {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<Result<usize>> {
+        match self.get_mut() {
+            Self::Tcp(stream) => Pin::new(stream).poll_write(cx, buf),
+            Self::Unix(stream) => Pin::new(stream).poll_write(cx, buf),
+        }
+    }
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        match self.get_mut() {
+            Self::Tcp(stream) => Pin::new(stream).poll_flush(cx),
+            Self::Unix(stream) => Pin::new(stream).poll_flush(cx),
+        }
+    }
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        match self.get_mut() {
+            Self::Tcp(stream) => Pin::new(stream).poll_shutdown(cx),
+            Self::Unix(stream) => Pin::new(stream).poll_shutdown(cx),
+        }
+    }
+}
+
+impl AsyncRead for Socket {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<Result<()>> {
+        match self.get_mut() {
+            Self::Tcp(stream) => Pin::new(stream).poll_read(cx, buf),
+            Self::Unix(stream) => Pin::new(stream).poll_read(cx, buf),
+        }
+    }
+}
+
+impl Connection for Socket {
+    fn endpoint(&self) -> Result<Endpoint> {
+        match self {
+            Self::Tcp(stream) => stream.peer_addr().map(Endpoint::Tcp),
+            Self::Unix(stream) => Ok(Endpoint::Unix(
+                stream
+                    .peer_addr()?
+                    .as_pathname()
+                    .map(Path::to_path_buf)
+                    .unwrap_or_default(),
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn unix_sockets_get_the_configured_mode() {
+        let directory = std::env::temp_dir().join(format!("auxv-dot-org-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("http.sock");
+        let permissions = UnixPermissions {
+            mode: 0o600,
+            group: None,
+        };
+
+        let listener = bind_unix(&path, &permissions).unwrap();
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        // Clients connect from unnamed sockets, so the endpoint is the peer's (empty) path rather
+        // than the listening socket's:
+        let _client = UnixStream::connect(&path).await.unwrap();
+        let (stream, _) = listener.accept().await.unwrap();
+        match Socket::Unix(stream).endpoint().unwrap() {
+            Endpoint::Unix(peer) => assert!(peer.as_os_str().is_empty()),
+            _ => panic!("Expected a Unix endpoint"),
+        }
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
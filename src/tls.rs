@@ -0,0 +1,92 @@
+//! 🔏 Helpers shared by the HTTPS listeners.
+
+use std::{
+    io::{Error, ErrorKind, Result},
+    pin::Pin,
+    task::{Context, Poll},
+    time::{Duration, SystemTime},
+};
+
+use rocket::listener::{Connection, Endpoint};
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    time::timeout,
+};
+use tokio_rustls::{rustls::sign::CertifiedKey, server::TlsStream};
+
+use crate::sockets::Socket;
+
+/// Application protocols offered during TLS handshakes, in order of preference (Rocket serves
+/// HTTP/2 to clients that pick `h2`).
+pub const ALPN_PROTOCOLS: [&[u8]; 2] = [b"h2", b"http/1.1"];
+
+/// How long a client gets to complete the TLS handshake.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Waits up to [`HANDSHAKE_TIMEOUT`] for the TLS `handshake` with `remote`, failures are logged
+/// and only drop this connection.
+pub async fn complete_handshake(
+    remote: Endpoint,
+    handshake: impl Future<Output = Result<TlsStream<Socket>>>,
+) -> Result<TlsConnection> {
+    let handshake = timeout(HANDSHAKE_TIMEOUT, handshake)
+        .await
+        .map_err(|_| Error::new(ErrorKind::TimedOut, "TLS handshake timed out"))
+        .flatten();
+    if let Err(error) = &handshake {
+        log::debug!("TLS handshake with {remote} failed: {error}");
+    }
+    let tls_stream = handshake?;
+    log::trace!(
+        "TLS connection from {remote} negotiated {}",
+        http_version(&tls_stream)
+    );
+    Ok(TlsConnection(tls_stream))
+}
+
+/// The HTTP version agreed on through ALPN (HTTP/1.1 for clients that don't use ALPN).
+fn http_version<IO>(tls_stream: &TlsStream<IO>) -> &'static str {
+    match tls_stream.get_ref().1.alpn_protocol() {
+        Some(b"h2") => "HTTP/2",
+        _ => "HTTP/1.1",
+    }
+}
+
+/// When the end-entity certificate expires, `None` if it can't be parsed.
+pub fn certificate_expiry(certified_key: &CertifiedKey) -> Option<SystemTime> {
+    let certificate = certified_key.end_entity_cert().ok()?;
+    let (_, certificate) = x509_parser::parse_x509_certificate(certificate).ok()?;
+    let not_after = certificate.validity().not_after.timestamp();
+    Some(SystemTime::UNIX_EPOCH + Duration::from_secs(not_after.try_into().ok()?))
+}
+
+/// 🔏⬆️⬇️ A connection established through either of the HTTPS listeners.
+pub struct TlsConnection(TlsStream<Socket>);
+
+impl AsyncWrite for TlsConnection {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<Result<usize>> {
+        Pin::new(&mut self.get_mut().0).poll_write(cx, buf)
+    }
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        Pin::new(&mut self.get_mut().0).poll_flush(cx)
+    }
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        Pin::new(&mut self.get_mut().0).poll_shutdown(cx)
+    }
+}
+
+impl AsyncRead for TlsConnection {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<Result<()>> {
+        Pin::new(&mut self.get_mut().0).poll_read(cx, buf)
+    }
+}
+
+impl Connection for TlsConnection {
+    fn endpoint(&self) -> Result<Endpoint> {
+        self.0.get_ref().0.endpoint()
+    }
+}
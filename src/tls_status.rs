@@ -0,0 +1,112 @@
+//! 📊 Certificate lifecycle tracking, reported by the authenticated `/_status` endpoints.
+
+use std::{
+    fmt::Write,
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use rocket::serde::Serialize;
+
+/// What has happened to the site's TLS certificate since the server started.
+///
+/// Updated by the HTTPS listener, and shared with Rocket as an `Arc<TlsStatus>`. Both listeners
+/// record the certificate's expiry, the renewal figures only change when using ACME.
+#[derive(Default, Debug)]
+pub struct TlsStatus(Mutex<TlsStatusReport>);
+
+/// A snapshot of the [`TlsStatus`], with times in seconds since the Unix epoch.
+#[derive(Serialize, Clone, Default, Debug)]
+#[serde(crate = "rocket::serde")]
+pub struct TlsStatusReport {
+    /// When the certificate currently being served expires.
+    pub certificate_expires_at: Option<u64>,
+    /// When a certificate was last requested from the ACME server (successfully or not).
+    pub last_renewal_attempt_at: Option<u64>,
+    /// Why the last request failed, `None` if it succeeded.
+    pub last_renewal_error: Option<String>,
+    pub certificates_issued: u64,
+    pub renewal_failures: u64,
+    pub cache_errors: u64,
+}
+
+impl TlsStatus {
+    pub fn report(&self) -> TlsStatusReport {
+        self.0.lock().unwrap().clone()
+    }
+
+    pub fn set_certificate_expiry(&self, expires_at: SystemTime) {
+        self.0.lock().unwrap().certificate_expires_at = Some(unix_time(expires_at));
+    }
+
+    pub fn renewal_succeeded(&self) {
+        let mut report = self.0.lock().unwrap();
+        report.last_renewal_attempt_at = Some(unix_time(SystemTime::now()));
+        report.last_renewal_error = None;
+        report.certificates_issued += 1;
+    }
+
+    pub fn renewal_failed(&self, error: String) {
+        let mut report = self.0.lock().unwrap();
+        report.last_renewal_attempt_at = Some(unix_time(SystemTime::now()));
+        report.last_renewal_error = Some(error);
+        report.renewal_failures += 1;
+    }
+
+    pub fn cache_failed(&self) {
+        self.0.lock().unwrap().cache_errors += 1;
+    }
+}
+
+impl TlsStatusReport {
+    /// Formats the report as metrics in the Prometheus text exposition format.
+    pub fn to_prometheus(&self) -> String {
+        let mut metrics = String::new();
+        let mut metric = |name: &str, kind: &str, help: &str, value: Option<u64>| {
+            if let Some(value) = value {
+                let _ = write!(
+                    metrics,
+                    "# HELP {name} {help}\n# TYPE {name} {kind}\n{name} {value}\n"
+                );
+            }
+        };
+
+        metric(
+            "auxv_tls_certificate_expiry_timestamp_seconds",
+            "gauge",
+            "When the served certificate expires.",
+            self.certificate_expires_at,
+        );
+        metric(
+            "auxv_tls_last_renewal_attempt_timestamp_seconds",
+            "gauge",
+            "When a certificate was last requested.",
+            self.last_renewal_attempt_at,
+        );
+        metric(
+            "auxv_tls_certificates_issued_total",
+            "counter",
+            "Certificates issued since the server started.",
+            Some(self.certificates_issued),
+        );
+        metric(
+            "auxv_tls_renewal_failures_total",
+            "counter",
+            "Failed certificate requests since the server started.",
+            Some(self.renewal_failures),
+        );
+        metric(
+            "auxv_tls_cache_errors_total",
+            "counter",
+            "Failed certificate or account cache reads and writes since the server started.",
+            Some(self.cache_errors),
+        );
+
+        metrics
+    }
+}
+
+fn unix_time(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
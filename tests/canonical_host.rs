@@ -0,0 +1,44 @@
+mod common;
+
+use auxv_dot_org::{build_rocket, config::SiteConfig, pages};
+use rocket::{
+    http::{Status, uri::Host},
+    local::blocking::Client,
+};
+
+#[test]
+fn alternate_domains_redirect_to_canonical() {
+    let config = SiteConfig {
+        domains: vec!["auxv.org".to_owned(), "www.auxv.org".to_owned()],
+        ..common::site_config()
+    };
+    let site = pages::render_site(&config).unwrap();
+    let client = Client::tracked(build_rocket(&config, site)).unwrap();
+
+    let mut request = client.get("/about?lang=en");
+    request.set_host(Host::parse("WWW.auxv.org").unwrap());
+    let response = request.dispatch();
+    assert_eq!(response.status(), Status::MovedPermanently);
+    assert_eq!(
+        response.headers().get_one("Location"),
+        Some("https://auxv.org/about?lang=en")
+    );
+
+    // Redirected before any route runs, so even missing pages and other methods are:
+    let mut request = client.post("/missing");
+    request.set_host(Host::parse("www.auxv.org").unwrap());
+    let response = request.dispatch();
+    assert_eq!(response.status(), Status::PermanentRedirect);
+    assert_eq!(
+        response.headers().get_one("Location"),
+        Some("https://auxv.org/missing")
+    );
+
+    let mut request = client.get("/");
+    request.set_host(Host::parse("auxv.org").unwrap());
+    assert_eq!(request.dispatch().status(), Status::Ok);
+
+    let mut request = client.get("/_canonical_host");
+    request.set_host(Host::parse("auxv.org").unwrap());
+    assert_eq!(request.dispatch().status(), Status::NotFound);
+}
@@ -0,0 +1,70 @@
+//! Fixtures shared by the integration tests, each of which only uses some of them.
+#![allow(dead_code)]
+
+use std::{
+    fs::{copy, create_dir_all, write},
+    path::{Path, PathBuf},
+};
+
+use auxv_dot_org::config::SiteConfig;
+use tempfile::TempDir;
+
+/// The repository's own `pages` directory.
+pub fn pages_dir() -> PathBuf {
+    PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/pages"))
+}
+
+/// The default configuration, serving the repository's own pages.
+pub fn site_config() -> SiteConfig {
+    SiteConfig {
+        pages_dir: Some(pages_dir()),
+        ..Default::default()
+    }
+}
+
+/// A pages directory of its own, with a template of just `{{html}}` and an empty emoji directory.
+/// It's deleted when dropped.
+pub struct TempPages(TempDir);
+
+impl TempPages {
+    pub fn new() -> Self {
+        let pages = Self(
+            tempfile::Builder::new()
+                .prefix("auxv-dot-org-")
+                .tempdir()
+                .unwrap(),
+        );
+        create_dir_all(pages.path().join("emojis")).unwrap();
+        pages.write("templates/template.html", "{{html}}")
+    }
+
+    pub fn path(&self) -> &Path {
+        self.0.path()
+    }
+
+    /// Writes `contents` to `path` in the directory, creating its parents.
+    pub fn write(self, path: &str, contents: &str) -> Self {
+        let path = self.path().join(path);
+        create_dir_all(path.parent().unwrap()).unwrap();
+        write(path, contents).unwrap();
+        self
+    }
+
+    /// Copies the emoji SVG `file_name` from the repository's pages.
+    pub fn with_emoji(self, file_name: &str) -> Self {
+        copy(
+            pages_dir().join("emojis").join(file_name),
+            self.path().join("emojis").join(file_name),
+        )
+        .unwrap();
+        self
+    }
+
+    /// The default configuration, serving these pages.
+    pub fn config(&self) -> SiteConfig {
+        SiteConfig {
+            pages_dir: Some(self.path().to_owned()),
+            ..Default::default()
+        }
+    }
+}
@@ -0,0 +1,121 @@
+mod common;
+
+use std::{
+    fs::File,
+    io::{Read, Write},
+    time::{Duration, SystemTime},
+};
+
+use auxv_dot_org::{build_rocket, compression::Encoding, pages};
+use flate2::{Compression, write::GzEncoder};
+use rocket::{
+    http::{ContentType, Header, Status},
+    local::blocking::Client,
+};
+
+use crate::common::TempPages;
+
+#[test]
+fn accept_encoding_is_negotiated() {
+    assert_eq!(
+        Encoding::accepted("gzip, deflate, br, zstd"),
+        [Encoding::Brotli, Encoding::Zstd, Encoding::Gzip]
+    );
+    assert_eq!(
+        Encoding::accepted("br;q=0, GZIP;q=0.8, zstd;q=0.9"),
+        [Encoding::Zstd, Encoding::Gzip]
+    );
+    assert!(Encoding::accepted("identity").is_empty());
+}
+
+#[test]
+fn pages_are_served_compressed() {
+    let config = common::site_config();
+    let site = pages::render_site(&config).unwrap();
+    let client = Client::tracked(build_rocket(&config, site)).unwrap();
+
+    let plain = client.get("/about").dispatch();
+    assert_eq!(plain.headers().get_one("Content-Encoding"), None);
+    assert_eq!(plain.headers().get_one("Vary"), Some("Accept-Encoding"));
+    let plain_etag = plain.headers().get_one("ETag").unwrap().to_owned();
+    let plain_body = plain.into_bytes().unwrap();
+
+    let compressed = client
+        .get("/about")
+        .header(Header::new("Accept-Encoding", "gzip, br"))
+        .dispatch();
+    assert_eq!(compressed.status(), Status::Ok);
+    assert_eq!(compressed.content_type(), Some(ContentType::HTML));
+    assert_eq!(compressed.headers().get_one("Content-Encoding"), Some("br"));
+    assert_ne!(
+        compressed.headers().get_one("ETag"),
+        Some(plain_etag.as_str())
+    );
+
+    for encoding in Encoding::ALL {
+        let compressed = client
+            .get("/about")
+            .header(Header::new("Accept-Encoding", encoding.name()))
+            .dispatch()
+            .into_bytes()
+            .unwrap();
+        assert_eq!(
+            decompress(encoding, &compressed),
+            plain_body,
+            "{encoding:?}"
+        );
+    }
+}
+
+#[test]
+fn files_are_served_from_current_precompressed_copies() {
+    let mut gzip = GzEncoder::new(Vec::new(), Compression::best());
+    gzip.write_all(b"console.log(1);").unwrap();
+    let pages = TempPages::new()
+        .write("index.md", "Hello")
+        .write("main.js", "console.log(1);");
+    std::fs::write(pages.path().join("main.js.gz"), gzip.finish().unwrap()).unwrap();
+    let config = pages.config();
+    let client =
+        Client::tracked(build_rocket(&config, pages::render_site(&config).unwrap())).unwrap();
+
+    let response = client
+        .get("/main.js")
+        .header(Header::new("Accept-Encoding", "gzip"))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.content_type(), Some(ContentType::JavaScript));
+    assert_eq!(response.headers().get_one("Content-Encoding"), Some("gzip"));
+    let body = response.into_bytes().unwrap();
+    assert_eq!(decompress(Encoding::Gzip, &body), b"console.log(1);");
+
+    // Edited since it was compressed, so the copy is stale:
+    let pages = pages.write("main.js", "console.log(2);");
+    File::options()
+        .write(true)
+        .open(pages.path().join("main.js"))
+        .unwrap()
+        .set_modified(SystemTime::now() + Duration::from_secs(60))
+        .unwrap();
+
+    let response = client
+        .get("/main.js")
+        .header(Header::new("Accept-Encoding", "gzip"))
+        .dispatch();
+    assert_eq!(response.headers().get_one("Content-Encoding"), None);
+    assert_eq!(response.into_string().unwrap(), "console.log(2);");
+}
+
+fn decompress(encoding: Encoding, body: &[u8]) -> Vec<u8> {
+    let mut decompressed = Vec::new();
+    match encoding {
+        Encoding::Brotli => brotli::Decompressor::new(body, 4096)
+            .read_to_end(&mut decompressed)
+            .unwrap(),
+        Encoding::Zstd => return zstd::decode_all(body).unwrap(),
+        Encoding::Gzip => flate2::read::GzDecoder::new(body)
+            .read_to_end(&mut decompressed)
+            .unwrap(),
+    };
+    decompressed
+}
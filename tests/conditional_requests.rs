@@ -0,0 +1,124 @@
+mod common;
+
+use std::time::SystemTime;
+
+use auxv_dot_org::{
+    build_rocket,
+    config::{CacheControl, SiteConfig},
+    pages,
+};
+use rocket::{
+    http::{Header, Status},
+    local::blocking::Client,
+};
+
+use crate::common::TempPages;
+
+#[test]
+fn unchanged_pages_are_not_modified() {
+    let config = SiteConfig {
+        cache_control: CacheControl {
+            pages: Some("no-cache".to_owned()),
+            ..Default::default()
+        },
+        ..common::site_config()
+    };
+    let site = pages::render_site(&config).unwrap();
+    let client = Client::tracked(build_rocket(&config, site)).unwrap();
+
+    let response = client.get("/about").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let etag = response.headers().get_one("ETag").unwrap().to_owned();
+    assert_eq!(response.headers().get_one("Last-Modified"), None);
+
+    let response = client
+        .get("/about")
+        .header(Header::new("If-None-Match", format!("\"stale\", W/{etag}")))
+        .dispatch();
+    assert_eq!(response.status(), Status::NotModified);
+    assert_eq!(
+        response.headers().get_one("Cache-Control"),
+        Some("no-cache")
+    );
+    assert_eq!(response.headers().get_one("ETag"), Some(etag.as_str()));
+    assert!(response.into_bytes().unwrap_or_default().is_empty());
+}
+
+#[test]
+fn pages_change_with_their_template() {
+    let pages = TempPages::new().write("index.md", "Hello");
+    let config = pages.config();
+    let client =
+        Client::tracked(build_rocket(&config, pages::render_site(&config).unwrap())).unwrap();
+    let etag = client
+        .get("/")
+        .dispatch()
+        .headers()
+        .get_one("ETag")
+        .unwrap()
+        .to_owned();
+
+    // A deploy changing only the template, the page's markdown is as old as before:
+    let pages = pages.write("templates/template.html", "<main>{{html}}</main>");
+    let client =
+        Client::tracked(build_rocket(&config, pages::render_site(&config).unwrap())).unwrap();
+
+    let response = client
+        .get("/")
+        .header(Header::new("If-None-Match", etag))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let response = client
+        .get("/")
+        .header(Header::new(
+            "If-Modified-Since",
+            httpdate::fmt_http_date(SystemTime::now()),
+        ))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert!(response.into_string().unwrap().starts_with("<main>"));
+}
+
+#[test]
+fn unchanged_files_are_not_modified_since_their_date() {
+    let config = common::site_config();
+    let client =
+        Client::tracked(build_rocket(&config, pages::render_site(&config).unwrap())).unwrap();
+
+    let response = client.get("/robots.txt").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let last_modified = response
+        .headers()
+        .get_one("Last-Modified")
+        .unwrap()
+        .to_owned();
+
+    let response = client
+        .get("/robots.txt")
+        .header(Header::new("If-Modified-Since", last_modified.clone()))
+        .dispatch();
+    assert_eq!(response.status(), Status::NotModified);
+
+    // If-None-Match takes precedence over If-Modified-Since:
+    let response = client
+        .get("/robots.txt")
+        .header(Header::new("If-None-Match", "\"stale\""))
+        .header(Header::new("If-Modified-Since", last_modified))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+}
+
+#[test]
+fn files_get_the_policy_of_their_class() {
+    let cache_control = CacheControl {
+        fonts: Some("fonts".to_owned()),
+        files: Some("files".to_owned()),
+        ..Default::default()
+    };
+
+    let policy = |path: &str| cache_control.for_file(path.as_ref());
+    assert_eq!(policy("font/cascadia_code.woff2"), Some("fonts"));
+    assert_eq!(policy("favicon.svg"), Some("files"));
+    assert_eq!(policy("robots.txt"), Some("files"));
+}
@@ -0,0 +1,61 @@
+use std::{
+    fs::write,
+    net::{Ipv4Addr, Ipv6Addr},
+    path::PathBuf,
+};
+
+use auxv_dot_org::{
+    config::{BindAddress, SiteConfig},
+    pages::PageDates,
+};
+
+#[test]
+fn missing_keys_fall_back_to_defaults() {
+    let directory = tempfile::tempdir().unwrap();
+    let path = directory.path().join("site.toml");
+    write(
+        &path,
+        "domains = [\"example.com\", \"www.example.com\"]\n\
+        http_port = 8080\n\
+        [render]\n\
+        dates = \"git\"\n\
+        [features]\n\
+        search = false\n",
+    )
+    .unwrap();
+
+    let config = SiteConfig::load(&path).unwrap();
+
+    assert_eq!(config.primary_domain(), Some("example.com"));
+    assert_eq!(config.http_port, 8080);
+    assert_eq!(config.https_port, 443);
+    assert_eq!(config.render.dates, PageDates::Git);
+    assert!(config.render.tables);
+    assert!(!config.features.search);
+    assert!(config.features.emojis);
+}
+
+#[test]
+fn bind_addresses_parse() {
+    let directory = tempfile::tempdir().unwrap();
+    let path = directory.path().join("site.toml");
+    write(
+        &path,
+        "bind = [\"0.0.0.0\", \"[::]\", \"unix:/run/auxv\"]\n\
+        unix_socket_mode = 0o640\n",
+    )
+    .unwrap();
+
+    let config = SiteConfig::load(&path).unwrap();
+
+    assert_eq!(
+        config.bind,
+        [
+            BindAddress::Ip(Ipv4Addr::UNSPECIFIED.into()),
+            BindAddress::Ip(Ipv6Addr::UNSPECIFIED.into()),
+            BindAddress::Unix(PathBuf::from("/run/auxv")),
+        ]
+    );
+    assert_eq!(config.unix_socket_mode, 0o640);
+    assert!("localhost".parse::<BindAddress>().is_err());
+}
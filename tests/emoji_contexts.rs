@@ -0,0 +1,87 @@
+mod common;
+
+use std::path::Path;
+
+use auxv_dot_org::{build_rocket, pages};
+use rocket::local::blocking::Client;
+
+use crate::common::TempPages;
+
+#[test]
+fn emoji_are_only_substituted_in_text() {
+    let pages = TempPages::new()
+        .with_emoji("1f32e.svg")
+        .write(
+            "index.md",
+            r#"Taco 🌮 `inline 🌮` ![🌮 alt](/taco.png)
+
+```
+block 🌮
+```
+
+<h1 title="🌮">Heading 🌮</h1>
+<!-- 🌮 -->
+<pre>pre 🌮</pre>
+
+Raw <code>inline 🌮</code> <svg width="1"/> after 🌮
+
+Unclosed <code>inline 🌮
+
+Next paragraph 🌮
+"#,
+        )
+        .write(
+            "native.md",
+            "<head>\n<meta name=\"emojis\" content=\"native\">\n</head>\nTaco 🌮",
+        )
+        .write(
+            "native_loose.md",
+            "<head>\n<META content='native' name=emojis />\n</head>\nTaco 🌮",
+        );
+
+    let site = pages::render_site(&pages.config()).unwrap();
+
+    let html = site.pages[Path::new("index")].html.body;
+    // Only the paragraph text, the heading's text, after the self-closing `<svg/>` and in the
+    // paragraph after the unclosed `<code>`:
+    assert_eq!(html.matches("<svg class=\"emoji\"").count(), 4);
+    for literal in [
+        "<code>inline 🌮</code>",
+        "alt=\"🌮 alt\"",
+        "block 🌮",
+        "title=\"🌮\"",
+        "<!-- 🌮 -->",
+        "<pre>pre 🌮</pre>",
+    ] {
+        assert!(html.contains(literal), "{literal} was substituted");
+    }
+
+    for page in ["native", "native_loose"] {
+        let html = site.pages[Path::new(page)].html.body;
+        assert!(html.contains("Taco 🌮"), "{page} had its emoji substituted");
+    }
+}
+
+#[test]
+fn emoji_titles_are_not_page_titles() {
+    // 🌮 is 1f32e, whose inline SVG has a `<title>taco</title>`:
+    let pages = TempPages::new()
+        .with_emoji("1f32e.svg")
+        .write("untitled.md", "Taco time 🌮")
+        .write(
+            "titled.md",
+            "<head>\n<title>Tacos</title>\n</head>\nTaco time 🌮",
+        );
+    let config = pages.config();
+    let client =
+        Client::tracked(build_rocket(&config, pages::render_site(&config).unwrap())).unwrap();
+
+    let results = client
+        .get("/search?query=Taco%20time")
+        .dispatch()
+        .into_string()
+        .unwrap();
+    assert!(results.contains(r#""title":"untitled""#), "{results}");
+    assert!(results.contains(r#""title":"Tacos""#), "{results}");
+    assert!(!results.contains(r#""title":"taco""#), "{results}");
+}
@@ -0,0 +1,103 @@
+mod common;
+
+use std::path::Path;
+
+use auxv_dot_org::{
+    build_rocket,
+    emojis::EmojiMode,
+    pages::{self, Site},
+};
+use rocket::{
+    http::{ContentType, Status},
+    local::blocking::Client,
+};
+
+use crate::common::TempPages;
+
+#[test]
+fn emoji_reference_a_sprite_sheet() {
+    let mut config = common::site_config();
+    config.render.emoji_mode = EmojiMode::Sprite;
+    let client =
+        Client::tracked(build_rocket(&config, pages::render_site(&config).unwrap())).unwrap();
+    let site = client.rocket().state::<Site>().unwrap();
+
+    let sprite_url = format!("/{}", site.emoji_sprite.as_ref().unwrap().path.display());
+    assert!(sprite_url.starts_with("/emojis/sprite."));
+    assert!(sprite_url.ends_with(".svg"));
+
+    // 🌮 is 1f32e:
+    let html = site.pages[Path::new("about")].html.body;
+    assert!(html.contains(&format!(
+        "role=\"img\" aria-label=\"taco\" data-emoji=\"🌮\"><title>taco</title><use href=\"{sprite_url}#1f32e\"/></svg>"
+    )));
+    assert!(!html.contains("/emojis/sprite.svg"));
+
+    let response = client.get(&sprite_url).dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.content_type(), Some(ContentType::SVG));
+    assert!(
+        response
+            .headers()
+            .get_one("Cache-Control")
+            .is_some_and(|cache_control| cache_control.contains("immutable"))
+    );
+    let sprite = response.into_string().unwrap();
+    assert!(sprite.contains("<symbol id=\"1f32e\" viewBox=\"0 0 36 36\">"));
+    // Only the emoji used on the site:
+    assert!(!sprite.contains("id=\"1f004\""));
+
+    // Pages cached from before a deploy link to the sprite sheet of that deploy, or to the
+    // original URL from before it was fingerprinted, which both get the current one:
+    for url in ["/emojis/sprite.0123456789abcdef.svg", "/emojis/sprite.svg"] {
+        let response = client.get(url).dispatch();
+        assert_eq!(response.status(), Status::Ok, "{url}");
+        assert_eq!(
+            response.headers().get_one("Cache-Control"),
+            Some("no-cache"),
+            "{url}"
+        );
+        assert_eq!(response.into_string().unwrap(), sprite, "{url}");
+    }
+}
+
+#[test]
+fn emoji_reference_their_files() {
+    let mut config = common::site_config();
+    config.render.emoji_mode = EmojiMode::Image;
+    config.features.fingerprints = false;
+    let site = pages::render_site(&config).unwrap();
+
+    // 🌮 is 1f32e:
+    let html = site.pages[Path::new("about")].html.body;
+    assert!(
+        html.contains("alt=\"🌮\" title=\"taco\" data-emoji=\"🌮\" src=\"/emojis/1f32e.svg\">")
+    );
+}
+
+#[test]
+fn inline_emoji_are_named_for_screen_readers() {
+    // 🌮 is 1f32e:
+    let pages = TempPages::new()
+        .with_emoji("1f32e.svg")
+        .write("index.md", "Taco time 🌮");
+    let mut config = pages.config();
+    config.render.emoji_mode = EmojiMode::Inline;
+    let site = pages::render_site(&config).unwrap();
+
+    let html = site.pages[Path::new("index")].html.body;
+    assert!(html.contains(
+        "role=\"img\" aria-label=\"taco\" data-emoji=\"🌮\" xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"0 0 36 36\"><title>taco</title><path"
+    ));
+    assert_eq!(html.matches("<title>").count(), 1);
+}
+
+#[test]
+fn broken_emoji_files_are_errors() {
+    let pages = TempPages::new()
+        .write("emojis/1f32e.svg", "<html>not an emoji</html>")
+        .write("index.md", "Taco time 🌮");
+
+    let error = pages::render_site(&pages.config()).err().unwrap();
+    assert!(error.to_string().contains("1f32e.svg isn't an SVG"));
+}
@@ -0,0 +1,45 @@
+mod common;
+
+use std::path::Path;
+
+use auxv_dot_org::pages;
+
+use crate::common::TempPages;
+
+#[test]
+fn shortcodes_resolve_to_emoji() {
+    // 🌮 is 1f32e:
+    let pages = TempPages::new()
+        .with_emoji("1f32e.svg")
+        .write(
+            "index.md",
+            "Taco time :taco: at 12:30:00, :not_an_emoji: `:taco:`\n\n\
+            Not std::fs::read, host:port: or ::a:: but :wave: has no SVG",
+        )
+        .write(
+            "native.md",
+            "<head>\n<meta name=\"emojis\" content=\"native\">\n</head>\nTaco time :taco: :wave:",
+        );
+
+    let site = pages::render_site(&pages.config()).unwrap();
+
+    let page = site.pages[Path::new("index")];
+    assert_eq!(page.html.body.matches("<svg class=\"emoji\"").count(), 1);
+    assert!(page.html.body.contains("data-emoji=\"🌮\""));
+    assert!(
+        page.html
+            .body
+            .contains("at 12:30:00, :not_an_emoji: <code>:taco:</code>")
+    );
+    assert!(
+        page.html
+            .body
+            .contains("Not std::fs::read, host:port: or ::a:: but 👋 has no SVG")
+    );
+    assert_eq!(page.unknown_shortcodes, ["not_an_emoji", "wave"]);
+
+    // Native pages don't need the SVGs:
+    let page = site.pages[Path::new("native")];
+    assert!(page.html.body.contains("Taco time 🌮 👋"));
+    assert!(page.unknown_shortcodes.is_empty());
+}
@@ -0,0 +1,27 @@
+mod common;
+
+use std::path::Path;
+
+use auxv_dot_org::{export, pages};
+
+#[test]
+fn export_writes_pages_and_assets() {
+    let config = common::site_config();
+    let site = pages::render_site(&config).unwrap();
+
+    let output_dir = tempfile::tempdir().unwrap();
+    let output = output_dir.path();
+    export::export_site(&site, &config.content(), output).unwrap();
+
+    for path in [
+        "index.html",
+        "404.html",
+        "about/index.html",
+        "projects/franxx/index.html",
+        "favicon.svg",
+        "templates/template.css",
+    ] {
+        assert!(output.join(path).is_file(), "missing {path}");
+    }
+    assert!(!output.join(Path::new("about.md")).exists());
+}
@@ -0,0 +1,77 @@
+mod common;
+
+use std::path::Path;
+
+use auxv_dot_org::{
+    build_rocket,
+    pages::{self, Site},
+};
+use rocket::{
+    http::{ContentType, Status},
+    local::blocking::Client,
+};
+
+#[test]
+fn pages_link_to_fingerprinted_assets() {
+    let config = common::site_config();
+    let client =
+        Client::tracked(build_rocket(&config, pages::render_site(&config).unwrap())).unwrap();
+    let site = client.rocket().state::<Site>().unwrap();
+
+    let (fingerprinted, _) = site
+        .fingerprints
+        .iter()
+        .find(|(_, original)| *original == Path::new("templates/template.css"))
+        .unwrap();
+    let url = format!("/{}", fingerprinted.display());
+
+    let html = site.pages[Path::new("about")].html.body;
+    assert!(html.contains(&format!("href=\"{url}\"")));
+    assert!(!html.contains("\"/templates/template.css\""));
+
+    let response = client.get(&url).dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.content_type(), Some(ContentType::CSS));
+    assert!(
+        response
+            .headers()
+            .get_one("Cache-Control")
+            .is_some_and(|cache_control| cache_control.contains("immutable"))
+    );
+    let stylesheet = response.into_string().unwrap();
+
+    // Pages cached from before a deploy link to the fingerprints of that deploy, which get the
+    // current contents:
+    let response = client
+        .get("/templates/template.0123456789abcdef.css")
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.content_type(), Some(ContentType::CSS));
+    assert_eq!(
+        response.headers().get_one("Cache-Control"),
+        Some("no-cache")
+    );
+    assert_eq!(response.into_string().unwrap(), stylesheet);
+
+    // The original URL keeps working too:
+    let response = client.get("/templates/template.css").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.headers().get_one("Cache-Control"), None);
+
+    // Stylesheets link to fingerprinted URLs too:
+    let fingerprinted = |path: &str| {
+        site.fingerprints
+            .iter()
+            .find(|(_, original)| *original == Path::new(path))
+            .map(|(fingerprinted, _)| format!("/{}", fingerprinted.display()))
+    };
+
+    let stylesheet = fingerprinted("font/cascadia_code.css").unwrap();
+    let font = fingerprinted("font/cascadia_code.woff2").unwrap();
+    let css = client.get(&stylesheet).dispatch().into_string().unwrap();
+    assert!(css.contains(&format!("url(\"{font}\")")));
+    assert!(!css.contains("\"/font/cascadia_code.woff2\""));
+
+    // Only files the pages link to are hashed:
+    assert_eq!(fingerprinted("robots.txt"), None);
+}
@@ -0,0 +1,58 @@
+mod common;
+
+use std::{
+    path::Path,
+    process::Command,
+    time::{Duration, UNIX_EPOCH},
+};
+
+use auxv_dot_org::pages::{self, PageDates};
+
+use crate::common::TempPages;
+
+fn git(directory: &Path, args: &[&str], timestamp: u64) {
+    let status = Command::new("git")
+        .arg("-C")
+        .arg(directory)
+        .args(["-c", "user.name=Test", "-c", "user.email=test@example.com"])
+        .args(args)
+        .env("GIT_AUTHOR_DATE", format!("@{timestamp} +0000"))
+        .env("GIT_COMMITTER_DATE", format!("@{timestamp} +0000"))
+        .status()
+        .unwrap();
+    assert!(status.success(), "git {args:?} failed");
+}
+
+#[test]
+fn renamed_pages_keep_their_creation_date() {
+    let pages = TempPages::new().write("old.md", "A page that moves");
+    let pages_dir = pages.path();
+
+    git(pages_dir, &["init", "--quiet"], 0);
+    git(pages_dir, &["add", "."], 0);
+    git(
+        pages_dir,
+        &["commit", "--quiet", "-m", "Add a page"],
+        1_600_000_000,
+    );
+    git(pages_dir, &["mv", "old.md", "new.md"], 0);
+    git(
+        pages_dir,
+        &["commit", "--quiet", "-m", "Move it"],
+        1_700_000_000,
+    );
+
+    let mut config = pages.config();
+    config.render.dates = PageDates::Git;
+    let site = pages::render_site(&config).unwrap();
+
+    let page = site.pages[Path::new("new")];
+    assert_eq!(
+        page.created,
+        UNIX_EPOCH + Duration::from_secs(1_600_000_000)
+    );
+    assert_eq!(
+        page.last_modified,
+        UNIX_EPOCH + Duration::from_secs(1_700_000_000)
+    );
+}
@@ -1,6 +1,12 @@
+mod common;
+
 use std::{collections::HashMap, path::Path};
 
-use auxv_dot_org::{build_rocket, pages};
+use auxv_dot_org::{
+    build_rocket,
+    config::SiteConfig,
+    pages::{self, Site},
+};
 use rocket::{http::Status, local::blocking::Client};
 use scraper::{Html, Selector};
 
@@ -46,9 +52,9 @@ struct LinkChecker {
 }
 
 impl LinkChecker {
-    fn new() -> Self {
+    fn new(config: &SiteConfig, site: Site) -> Self {
         Self {
-            client: Client::untracked(build_rocket()).unwrap(),
+            client: Client::untracked(build_rocket(config, site)).unwrap(),
             anchor: Selector::parse("a[href]").unwrap(),
             image: Selector::parse("img[src]").unwrap(),
             script: Selector::parse("script[src]").unwrap(),
@@ -173,10 +179,12 @@ impl LinkChecker {
 
 #[test]
 fn all_links_resolve() {
-    pages::set_page_cache().unwrap();
-    let checker = LinkChecker::new();
+    let config = common::site_config();
+    let checker = LinkChecker::new(&config, pages::render_site(&config).unwrap());
+    let site = checker.client.rocket().state::<Site>().unwrap();
 
-    let failures: Vec<_> = pages::get_page_cache()
+    let failures: Vec<_> = site
+        .pages
         .keys()
         .flat_map(|path| {
             let url = if *path == Path::new("index") {
@@ -194,4 +202,21 @@ fn all_links_resolve() {
         failures.len(),
         failures.join("\n  - ")
     );
+
+    let unknown_shortcodes: Vec<_> = site
+        .pages
+        .iter()
+        .flat_map(|(path, page)| {
+            page.unknown_shortcodes
+                .iter()
+                .map(move |shortcode| format!("[/{}] :{shortcode}:", path.display()))
+        })
+        .collect();
+
+    assert!(
+        unknown_shortcodes.is_empty(),
+        "\n\n{} unknown emoji shortcode(s):\n  - {}\n",
+        unknown_shortcodes.len(),
+        unknown_shortcodes.join("\n  - ")
+    );
 }
@@ -0,0 +1,70 @@
+mod common;
+
+use std::path::Path;
+
+use auxv_dot_org::pages;
+
+use crate::common::TempPages;
+
+#[test]
+fn long_posts_show_reading_time() {
+    let config = common::site_config();
+    let site = pages::render_site(&config).unwrap();
+
+    let page = site.pages[Path::new("projects/franxx/how_not_to_build_a_keyboard")];
+
+    assert!(page.word_count > 1000, "only {} words", page.word_count);
+    assert!(page.reading_time > 1);
+    assert!(
+        page.html
+            .body
+            .contains(&format!("{} min read", page.reading_time))
+    );
+}
+
+#[test]
+fn default_metadata_is_escaped() {
+    let pages = TempPages::new()
+        .write("templates/template.html", "{{head}}")
+        .write("index.md", "Hello");
+    let mut config = pages.config();
+    config.metadata.title = Some("Tom & Jerry's <blog>".to_owned());
+    config.metadata.description = Some("A \"quoted\" description".to_owned());
+    let site = pages::render_site(&config).unwrap();
+
+    let html = site.pages[Path::new("index")].html.body;
+    assert!(html.contains("<title>Tom &amp; Jerry&apos;s &lt;blog&gt;</title>"));
+    assert!(html.contains("content=\"A &quot;quoted&quot; description\""));
+}
+
+#[test]
+fn self_closing_svgs_do_not_stop_the_word_count() {
+    let pages = TempPages::new().write(
+        "index.md",
+        "One <svg width=\"1\"/> two\n\n<svg><text>not prose</text></svg>\n\nthree four",
+    );
+    let site = pages::render_site(&pages.config()).unwrap();
+
+    assert_eq!(site.pages[Path::new("index")].word_count, 4);
+}
+
+#[test]
+fn placeholders_in_pages_are_left_alone() {
+    let pages = TempPages::new()
+        .write(
+            "templates/template.html",
+            "{{html}}<p>{{word_count}} words</p>",
+        )
+        .write(
+            "index.md",
+            "Write `{{word_count}}` or {{head}} in the template",
+        );
+    let site = pages::render_site(&pages.config()).unwrap();
+
+    let html = site.pages[Path::new("index")].html.body;
+    assert!(
+        html.contains("<code>{{word_count}}</code> or {{head}} in"),
+        "{html}"
+    );
+    assert!(html.contains("<p>7 words</p>"), "{html}");
+}
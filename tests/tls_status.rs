@@ -0,0 +1,75 @@
+mod common;
+
+use std::sync::Arc;
+
+use auxv_dot_org::{build_rocket, config::SiteConfig, pages, tls_status::TlsStatus};
+use rocket::{
+    http::{Header, Status},
+    local::blocking::Client,
+};
+
+#[test]
+fn status_endpoints_require_the_token() {
+    let config = SiteConfig {
+        status_token: Some("hunter2".to_owned()),
+        ..common::site_config()
+    };
+    let site = pages::render_site(&config).unwrap();
+    let client = Client::tracked(build_rocket(&config, site)).unwrap();
+    let tls_status = client.rocket().state::<Arc<TlsStatus>>().unwrap();
+    tls_status.renewal_failed("rate limited".to_owned());
+
+    let response = client.get("/_status/tls").dispatch();
+    assert_eq!(response.status(), Status::Unauthorized);
+    assert_eq!(
+        response.headers().get_one("WWW-Authenticate"),
+        Some("Bearer")
+    );
+    assert_eq!(
+        client
+            .get("/_status/tls")
+            .header(Header::new("Authorization", "Bearer hunter3"))
+            .dispatch()
+            .status(),
+        Status::Unauthorized
+    );
+
+    let response = client
+        .get("/_status/tls")
+        .header(Header::new("Authorization", "Bearer hunter2"))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let report = response.into_string().unwrap();
+    assert!(
+        report.contains(r#""last_renewal_error":"rate limited""#),
+        "{report}"
+    );
+    assert!(report.contains(r#""renewal_failures":1"#), "{report}");
+
+    let metrics = client
+        .get("/_status/metrics")
+        .header(Header::new("Authorization", "Bearer hunter2"))
+        .dispatch()
+        .into_string()
+        .unwrap();
+    assert!(
+        metrics.contains("auxv_tls_renewal_failures_total 1\n"),
+        "{metrics}"
+    );
+}
+
+#[test]
+fn empty_tokens_leave_the_status_endpoints_unmounted() {
+    let config = SiteConfig {
+        status_token: Some(String::new()),
+        ..common::site_config()
+    };
+    let site = pages::render_site(&config).unwrap();
+    let client = Client::tracked(build_rocket(&config, site)).unwrap();
+
+    let response = client
+        .get("/_status/tls")
+        .header(Header::new("Authorization", "Bearer "))
+        .dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+}